@@ -0,0 +1,86 @@
+// Local analytics service which logs received reports.
+// Run with: cargo run --example mock_analytics_server
+// MOCK_ANALYTICS_ADDRESS sets listening address (0.0.0.0:50053 by default),
+// MOCK_ANALYTICS_FAIL_FIRST makes first N requests fail to check retries
+use delivery_order::utils::grpc::analytics_grpc::analytics_server::{Analytics, AnalyticsServer};
+use delivery_order::utils::grpc::analytics_grpc::{
+    SaveOrderRequest, SaveOrderResponse, SaveRegRequest, SaveRegResponse,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tonic::{Request, Response, Status};
+
+struct MockAnalytics {
+    fail_first: u32,
+    received: AtomicU32,
+}
+
+impl MockAnalytics {
+    // True while first "fail_first" requests are received
+    fn should_fail(&self) -> bool {
+        let number = self.received.fetch_add(1, Ordering::SeqCst) + 1;
+        if number <= self.fail_first {
+            tracing::warn!(number, "Failing request on purpose");
+            return true;
+        }
+        false
+    }
+}
+
+#[tonic::async_trait]
+impl Analytics for MockAnalytics {
+    async fn save_reg_info(
+        &self,
+        request: Request<SaveRegRequest>,
+    ) -> Result<Response<SaveRegResponse>, Status> {
+        if self.should_fail() {
+            return Err(Status::unavailable("Mock analytics is unavailable"));
+        }
+        let request = request.into_inner();
+        tracing::info!(uuid = %request.uuid, role = %request.role, created_at = %request.created_at, "Registration saved");
+        Ok(Response::new(SaveRegResponse {
+            record_created: true,
+        }))
+    }
+
+    async fn save_order_info(
+        &self,
+        request: Request<SaveOrderRequest>,
+    ) -> Result<Response<SaveOrderResponse>, Status> {
+        if self.should_fail() {
+            return Err(Status::unavailable("Mock analytics is unavailable"));
+        }
+        let request = request.into_inner();
+        tracing::info!(
+            order_uuid = %request.order_uuid,
+            finished_at = %request.finished_at,
+            "Order saved"
+        );
+        for item in &request.order_items {
+            tracing::info!(product_uuid = %item.product_uuid, amount = item.amount, "Order item");
+        }
+        Ok(Response::new(SaveOrderResponse {
+            record_created: true,
+        }))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    tracing_subscriber::fmt().init();
+    let address = std::env::var("MOCK_ANALYTICS_ADDRESS").unwrap_or("0.0.0.0:50053".to_string());
+    let fail_first = match std::env::var("MOCK_ANALYTICS_FAIL_FIRST") {
+        Ok(value) => value.parse()?,
+        Err(_) => 0,
+    };
+    let service = MockAnalytics {
+        fail_first,
+        received: AtomicU32::new(0),
+    };
+
+    tracing::info!(%address, fail_first, "Mock analytics server is running");
+    tonic::transport::Server::builder()
+        .add_service(AnalyticsServer::new(service))
+        .serve(address.parse()?)
+        .await?;
+    Ok(())
+}
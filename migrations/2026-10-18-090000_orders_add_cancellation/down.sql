@@ -0,0 +1,3 @@
+ALTER TABLE orders
+    DROP COLUMN canceled_by,
+    DROP COLUMN cancel_reason;
@@ -0,0 +1,3 @@
+ALTER TABLE orders
+    ADD COLUMN canceled_by UUID,
+    ADD COLUMN cancel_reason TEXT;
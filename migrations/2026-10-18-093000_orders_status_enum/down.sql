@@ -0,0 +1,16 @@
+ALTER TABLE orders
+    ALTER COLUMN status DROP DEFAULT,
+    ALTER COLUMN status TYPE TEXT USING (
+        CASE status
+            WHEN 'FINISHED' THEN 'FINISHED'
+            WHEN 'CANCELED' THEN 'CANCELED'
+            ELSE 'IN_PROGRESS'
+        END
+    ),
+    ALTER COLUMN status SET DEFAULT 'IN_PROGRESS';
+
+ALTER TABLE orders
+    ADD CONSTRAINT USERS_ROLE_CHECK
+        CHECK (status in ('IN_PROGRESS', 'FINISHED', 'CANCELED'));
+
+DROP TYPE order_status;
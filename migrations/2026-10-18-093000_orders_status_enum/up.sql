@@ -0,0 +1,16 @@
+CREATE TYPE order_status AS ENUM (
+    'WAITING_FOR_COURIER',
+    'ASSIGNED',
+    'PICKED_UP',
+    'IN_PROGRESS',
+    'FINISHED',
+    'CANCELED'
+);
+
+ALTER TABLE orders
+    DROP CONSTRAINT USERS_ROLE_CHECK;
+
+ALTER TABLE orders
+    ALTER COLUMN status DROP DEFAULT,
+    ALTER COLUMN status TYPE order_status USING status::order_status,
+    ALTER COLUMN status SET DEFAULT 'ASSIGNED';
@@ -0,0 +1,20 @@
+CREATE TABLE order_status_history (
+    id BIGSERIAL PRIMARY KEY,
+    order_uuid UUID NOT NULL,
+    changed_by UUID,
+    from_status order_status,
+    to_status order_status NOT NULL,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT FK_ORDER
+        FOREIGN KEY(order_uuid)
+            REFERENCES orders(uuid)
+);
+
+CREATE INDEX idx_order_status_history ON order_status_history (order_uuid, created_at);
+
+-- Previous status changes were not recorded,
+-- so existing orders start their timeline with current status
+INSERT INTO order_status_history (order_uuid, to_status, note, created_at)
+    SELECT uuid, status, 'Imported from existing order', updated_at
+    FROM orders;
@@ -0,0 +1,13 @@
+CREATE TABLE order_idempotency_key (
+    user_uuid UUID NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    order_uuid UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_uuid, idempotency_key),
+    CONSTRAINT FK_ORDER
+        FOREIGN KEY(order_uuid)
+            REFERENCES orders(uuid)
+);
+
+CREATE INDEX idx_order_idempotency_key_expires_at ON order_idempotency_key (expires_at);
@@ -0,0 +1,6 @@
+ALTER TABLE orders
+    DROP COLUMN delivery_fee;
+
+ALTER TABLE order_item
+    DROP COLUMN product_name,
+    DROP COLUMN unit_price;
@@ -0,0 +1,18 @@
+ALTER TABLE order_item
+    ADD COLUMN product_name TEXT,
+    ADD COLUMN unit_price FLOAT;
+
+-- Historical orders get current product values,
+-- it is the best information available at this point
+UPDATE order_item
+    SET product_name = product.name,
+        unit_price = product.price
+    FROM product
+    WHERE product.uuid = order_item.product_uuid;
+
+ALTER TABLE order_item
+    ALTER COLUMN product_name SET NOT NULL,
+    ALTER COLUMN unit_price SET NOT NULL;
+
+ALTER TABLE orders
+    ADD COLUMN delivery_fee FLOAT NOT NULL DEFAULT 0;
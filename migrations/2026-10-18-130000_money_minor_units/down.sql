@@ -0,0 +1,12 @@
+ALTER TABLE orders
+    DROP COLUMN currency,
+    ALTER COLUMN delivery_fee DROP DEFAULT,
+    ALTER COLUMN delivery_fee TYPE FLOAT USING delivery_fee / 100.0,
+    ALTER COLUMN delivery_fee SET DEFAULT 0;
+
+ALTER TABLE order_item
+    ALTER COLUMN unit_price TYPE FLOAT USING unit_price / 100.0;
+
+ALTER TABLE product
+    DROP COLUMN currency,
+    ALTER COLUMN price TYPE FLOAT USING price / 100.0;
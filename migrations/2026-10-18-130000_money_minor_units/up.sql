@@ -0,0 +1,17 @@
+-- Prices are stored in minor units of currency (cents)
+ALTER TABLE product
+    ALTER COLUMN price TYPE BIGINT USING ROUND(price * 100)::BIGINT,
+    ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD',
+    ADD CONSTRAINT PRODUCT_CURRENCY_CHECK
+        CHECK (currency ~ '^[A-Z]{3}$');
+
+ALTER TABLE order_item
+    ALTER COLUMN unit_price TYPE BIGINT USING ROUND(unit_price * 100)::BIGINT;
+
+ALTER TABLE orders
+    ALTER COLUMN delivery_fee DROP DEFAULT,
+    ALTER COLUMN delivery_fee TYPE BIGINT USING ROUND(delivery_fee * 100)::BIGINT,
+    ALTER COLUMN delivery_fee SET DEFAULT 0,
+    ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD',
+    ADD CONSTRAINT ORDERS_CURRENCY_CHECK
+        CHECK (currency ~ '^[A-Z]{3}$');
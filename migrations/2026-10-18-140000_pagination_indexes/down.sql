@@ -0,0 +1,2 @@
+DROP INDEX idx_product_created_at_uuid;
+DROP INDEX idx_orders_created_at_uuid;
@@ -0,0 +1,2 @@
+CREATE INDEX idx_orders_created_at_uuid ON orders (created_at, uuid);
+CREATE INDEX idx_product_created_at_uuid ON product (created_at, uuid);
@@ -0,0 +1,6 @@
+DROP INDEX idx_product_restaurant_trgm;
+DROP INDEX idx_product_product_type_trgm;
+DROP INDEX idx_product_name_trgm;
+DROP INDEX idx_product_search_vector;
+
+ALTER TABLE product DROP COLUMN search_vector;
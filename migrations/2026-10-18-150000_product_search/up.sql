@@ -0,0 +1,13 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+ALTER TABLE product
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', product_type), 'B') ||
+        setweight(to_tsvector('simple', restaurant), 'C')
+    ) STORED;
+
+CREATE INDEX idx_product_search_vector ON product USING GIN (search_vector);
+CREATE INDEX idx_product_name_trgm ON product USING GIN (name gin_trgm_ops);
+CREATE INDEX idx_product_product_type_trgm ON product USING GIN (product_type gin_trgm_ops);
+CREATE INDEX idx_product_restaurant_trgm ON product USING GIN (restaurant gin_trgm_ops);
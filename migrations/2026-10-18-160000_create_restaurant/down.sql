@@ -0,0 +1,26 @@
+DROP INDEX idx_restaurant_name_trgm;
+ALTER TABLE product DROP COLUMN search_vector;
+
+ALTER TABLE product ADD COLUMN restaurant TEXT;
+
+UPDATE product
+SET restaurant = restaurant.name
+FROM restaurant
+WHERE restaurant.uuid = product.restaurant_uuid;
+
+ALTER TABLE product ALTER COLUMN restaurant SET NOT NULL;
+ALTER TABLE product DROP COLUMN restaurant_uuid;
+
+ALTER TABLE product
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', product_type), 'B') ||
+        setweight(to_tsvector('simple', restaurant), 'C')
+    ) STORED;
+
+CREATE INDEX idx_product_search_vector ON product USING GIN (search_vector);
+CREATE INDEX idx_product_restaurant_trgm ON product USING GIN (restaurant gin_trgm_ops);
+
+DROP TABLE restaurant_holiday;
+DROP TABLE restaurant_opening_hours;
+DROP TABLE restaurant;
@@ -0,0 +1,81 @@
+CREATE TABLE restaurant (
+    uuid UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    address TEXT,
+    phone TEXT,
+    email TEXT,
+    -- IANA time zone, so opening hours follow daylight saving time
+    time_zone TEXT NOT NULL DEFAULT 'UTC',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT RESTAURANT_NAME_UNIQUE UNIQUE (name)
+);
+
+-- Weekday is ISO day of week: 1 is Monday, 7 is Sunday.
+-- Hours past midnight are stored as a separate interval of the next day
+CREATE TABLE restaurant_opening_hours (
+    id BIGSERIAL PRIMARY KEY,
+    restaurant_uuid UUID NOT NULL,
+    weekday SMALLINT NOT NULL,
+    opens_at TIME NOT NULL,
+    closes_at TIME NOT NULL,
+    CONSTRAINT FK_RESTAURANT
+        FOREIGN KEY(restaurant_uuid)
+            REFERENCES restaurant(uuid)
+            ON DELETE CASCADE,
+    CONSTRAINT OPENING_HOURS_WEEKDAY_CHECK CHECK (weekday BETWEEN 1 AND 7),
+    CONSTRAINT OPENING_HOURS_INTERVAL_CHECK CHECK (opens_at < closes_at)
+);
+
+CREATE INDEX idx_restaurant_opening_hours_restaurant_uuid
+    ON restaurant_opening_hours (restaurant_uuid, weekday);
+
+-- Exception for a single date, restaurant is closed the whole day
+-- when hours are not set
+CREATE TABLE restaurant_holiday (
+    restaurant_uuid UUID NOT NULL,
+    date DATE NOT NULL,
+    opens_at TIME,
+    closes_at TIME,
+    note TEXT,
+    PRIMARY KEY (restaurant_uuid, date),
+    CONSTRAINT FK_RESTAURANT
+        FOREIGN KEY(restaurant_uuid)
+            REFERENCES restaurant(uuid)
+            ON DELETE CASCADE,
+    CONSTRAINT HOLIDAY_INTERVAL_CHECK CHECK (
+        (opens_at IS NULL AND closes_at IS NULL)
+        OR (opens_at IS NOT NULL AND closes_at IS NOT NULL AND opens_at < closes_at)
+    )
+);
+
+INSERT INTO restaurant (name)
+SELECT DISTINCT trim(restaurant) FROM product;
+
+ALTER TABLE product ADD COLUMN restaurant_uuid UUID;
+
+UPDATE product
+SET restaurant_uuid = restaurant.uuid
+FROM restaurant
+WHERE restaurant.name = trim(product.restaurant);
+
+ALTER TABLE product
+    ALTER COLUMN restaurant_uuid SET NOT NULL,
+    ADD CONSTRAINT FK_RESTAURANT
+        FOREIGN KEY(restaurant_uuid)
+            REFERENCES restaurant(uuid);
+
+CREATE INDEX idx_product_restaurant_uuid ON product (restaurant_uuid);
+
+-- Restaurant name is matched through restaurant table now
+DROP INDEX idx_product_restaurant_trgm;
+ALTER TABLE product DROP COLUMN search_vector;
+ALTER TABLE product DROP COLUMN restaurant;
+ALTER TABLE product
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', product_type), 'B')
+    ) STORED;
+
+CREATE INDEX idx_product_search_vector ON product USING GIN (search_vector);
+CREATE INDEX idx_restaurant_name_trgm ON restaurant USING GIN (name gin_trgm_ops);
@@ -0,0 +1,23 @@
+DROP INDEX idx_category_display_name_trgm;
+ALTER TABLE product DROP COLUMN search_vector;
+
+ALTER TABLE product ADD COLUMN product_type TEXT;
+
+UPDATE product
+SET product_type = category.name
+FROM category
+WHERE category.uuid = product.category_uuid;
+
+ALTER TABLE product ALTER COLUMN product_type SET NOT NULL;
+ALTER TABLE product DROP COLUMN category_uuid;
+
+ALTER TABLE product
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', product_type), 'B')
+    ) STORED;
+
+CREATE INDEX idx_product_search_vector ON product USING GIN (search_vector);
+CREATE INDEX idx_product_product_type_trgm ON product USING GIN (product_type gin_trgm_ops);
+
+DROP TABLE category;
@@ -0,0 +1,46 @@
+CREATE TABLE category (
+    uuid UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    parent_uuid UUID,
+    name TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT CATEGORY_NAME_UNIQUE UNIQUE (name),
+    CONSTRAINT FK_PARENT
+        FOREIGN KEY(parent_uuid)
+            REFERENCES category(uuid),
+    CONSTRAINT CATEGORY_PARENT_CHECK CHECK (parent_uuid <> uuid)
+);
+
+CREATE INDEX idx_category_parent_uuid ON category (parent_uuid);
+
+INSERT INTO category (name, display_name)
+SELECT DISTINCT lower(trim(product_type)), initcap(trim(product_type)) FROM product;
+
+ALTER TABLE product ADD COLUMN category_uuid UUID;
+
+UPDATE product
+SET category_uuid = category.uuid
+FROM category
+WHERE category.name = lower(trim(product.product_type));
+
+ALTER TABLE product
+    ALTER COLUMN category_uuid SET NOT NULL,
+    ADD CONSTRAINT FK_CATEGORY
+        FOREIGN KEY(category_uuid)
+            REFERENCES category(uuid);
+
+CREATE INDEX idx_product_category_uuid ON product (category_uuid);
+
+-- Category name is matched through category table now
+DROP INDEX idx_product_product_type_trgm;
+ALTER TABLE product DROP COLUMN search_vector;
+ALTER TABLE product DROP COLUMN product_type;
+ALTER TABLE product
+    ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A')
+    ) STORED;
+
+CREATE INDEX idx_product_search_vector ON product USING GIN (search_vector);
+CREATE INDEX idx_category_display_name_trgm ON category USING GIN (display_name gin_trgm_ops);
@@ -0,0 +1,7 @@
+DROP INDEX idx_restaurant_account_uuid;
+ALTER TABLE restaurant DROP COLUMN account_uuid;
+
+ALTER TABLE product
+    DROP CONSTRAINT PRODUCT_STOCK_CHECK,
+    DROP COLUMN is_available,
+    DROP COLUMN stock;
@@ -0,0 +1,10 @@
+-- Stock is not tracked when NULL
+ALTER TABLE product
+    ADD COLUMN stock INTEGER,
+    ADD COLUMN is_available BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD CONSTRAINT PRODUCT_STOCK_CHECK CHECK (stock >= 0);
+
+-- User account managing restaurant stock
+ALTER TABLE restaurant ADD COLUMN account_uuid UUID;
+
+CREATE INDEX idx_restaurant_account_uuid ON restaurant (account_uuid);
@@ -0,0 +1,2 @@
+DROP INDEX idx_product_not_archived;
+ALTER TABLE product DROP COLUMN archived_at;
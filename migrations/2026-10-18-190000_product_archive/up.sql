@@ -0,0 +1,4 @@
+-- Archived products are kept for order history
+ALTER TABLE product ADD COLUMN archived_at TIMESTAMP;
+
+CREATE INDEX idx_product_not_archived ON product (created_at, uuid) WHERE archived_at IS NULL;
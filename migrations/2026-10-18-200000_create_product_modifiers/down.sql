@@ -0,0 +1,17 @@
+ALTER TABLE order_item
+    DROP COLUMN option_names,
+    DROP COLUMN option_uuids;
+
+DELETE FROM bucket
+WHERE id NOT IN (
+    SELECT MIN(id) FROM bucket GROUP BY user_uuid, product_uuid
+);
+
+ALTER TABLE bucket
+    DROP CONSTRAINT unique_product_options_in_user_bucket,
+    DROP COLUMN option_uuids,
+    ADD CONSTRAINT unique_product_in_user_bucket
+        UNIQUE (user_uuid, product_uuid);
+
+DROP TABLE modifier_option;
+DROP TABLE modifier_group;
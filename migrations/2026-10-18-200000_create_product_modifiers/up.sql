@@ -0,0 +1,44 @@
+CREATE TABLE modifier_group (
+    uuid UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    product_uuid UUID NOT NULL,
+    name TEXT NOT NULL,
+    min_select INTEGER NOT NULL DEFAULT 0,
+    max_select INTEGER NOT NULL DEFAULT 1,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT FK_PRODUCT
+        FOREIGN KEY(product_uuid)
+            REFERENCES product(uuid)
+            ON DELETE CASCADE,
+    CONSTRAINT MODIFIER_GROUP_SELECT_CHECK
+        CHECK (min_select >= 0 AND max_select >= 1 AND max_select >= min_select)
+);
+
+CREATE INDEX idx_modifier_group_product_uuid ON modifier_group (product_uuid);
+
+CREATE TABLE modifier_option (
+    uuid UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    group_uuid UUID NOT NULL,
+    name TEXT NOT NULL,
+    price_delta BIGINT NOT NULL DEFAULT 0,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT FK_MODIFIER_GROUP
+        FOREIGN KEY(group_uuid)
+            REFERENCES modifier_group(uuid)
+            ON DELETE CASCADE
+);
+
+CREATE INDEX idx_modifier_option_group_uuid ON modifier_option (group_uuid);
+
+-- Selected options are stored sorted, so the same selection is the same bucket line
+ALTER TABLE bucket
+    ADD COLUMN option_uuids UUID[] NOT NULL DEFAULT '{}',
+    DROP CONSTRAINT unique_product_in_user_bucket,
+    ADD CONSTRAINT unique_product_options_in_user_bucket
+        UNIQUE (user_uuid, product_uuid, option_uuids);
+
+-- Option names are kept as they were when order was created
+ALTER TABLE order_item
+    ADD COLUMN option_uuids UUID[] NOT NULL DEFAULT '{}',
+    ADD COLUMN option_names TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE bucket DROP CONSTRAINT BUCKET_AMOUNT_CHECK;
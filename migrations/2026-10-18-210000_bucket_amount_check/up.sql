@@ -0,0 +1,4 @@
+DELETE FROM bucket WHERE amount <= 0;
+
+ALTER TABLE bucket
+    ADD CONSTRAINT BUCKET_AMOUNT_CHECK CHECK (amount > 0);
@@ -0,0 +1 @@
+DROP INDEX idx_bucket_updated_at;
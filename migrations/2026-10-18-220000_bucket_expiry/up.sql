@@ -0,0 +1 @@
+CREATE INDEX idx_bucket_updated_at ON bucket (updated_at);
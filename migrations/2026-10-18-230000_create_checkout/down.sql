@@ -0,0 +1,13 @@
+-- Keys of checkouts with several orders cannot point to a single order
+DELETE FROM order_idempotency_key
+    WHERE checkout_uuid NOT IN (SELECT uuid FROM orders);
+ALTER TABLE order_idempotency_key DROP CONSTRAINT FK_CHECKOUT;
+ALTER TABLE order_idempotency_key RENAME COLUMN checkout_uuid TO order_uuid;
+ALTER TABLE order_idempotency_key
+    ADD CONSTRAINT FK_ORDER
+        FOREIGN KEY(order_uuid)
+            REFERENCES orders(uuid);
+
+DROP INDEX idx_orders_checkout_uuid;
+ALTER TABLE orders DROP COLUMN checkout_uuid;
+DROP TABLE checkout;
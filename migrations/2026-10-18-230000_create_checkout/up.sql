@@ -0,0 +1,31 @@
+-- Checkout groups orders created from one bucket,
+-- one order per restaurant
+CREATE TABLE checkout (
+    uuid UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    user_uuid UUID NOT NULL,
+    address TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Existing orders become checkouts with a single order
+INSERT INTO checkout (uuid, user_uuid, address, currency, created_at)
+    SELECT uuid, user_uuid, address, currency, created_at FROM orders;
+
+ALTER TABLE orders ADD COLUMN checkout_uuid UUID;
+UPDATE orders SET checkout_uuid = uuid;
+ALTER TABLE orders
+    ALTER COLUMN checkout_uuid SET NOT NULL,
+    ADD CONSTRAINT FK_CHECKOUT
+        FOREIGN KEY(checkout_uuid)
+            REFERENCES checkout(uuid);
+
+CREATE INDEX idx_orders_checkout_uuid ON orders (checkout_uuid);
+
+-- Idempotency key now points to the whole checkout
+ALTER TABLE order_idempotency_key DROP CONSTRAINT FK_ORDER;
+ALTER TABLE order_idempotency_key RENAME COLUMN order_uuid TO checkout_uuid;
+ALTER TABLE order_idempotency_key
+    ADD CONSTRAINT FK_CHECKOUT
+        FOREIGN KEY(checkout_uuid)
+            REFERENCES checkout(uuid);
@@ -0,0 +1,34 @@
+DROP INDEX idx_orders_waiting_for_courier;
+
+-- Orders which never got a courier cannot be kept
+DELETE FROM order_status_history
+    WHERE order_uuid IN (SELECT uuid FROM orders WHERE courier_uuid IS NULL);
+DELETE FROM order_item
+    WHERE order_uuid IN (SELECT uuid FROM orders WHERE courier_uuid IS NULL);
+DELETE FROM orders WHERE courier_uuid IS NULL;
+
+ALTER TABLE orders
+    DROP COLUMN restaurant_uuid,
+    ALTER COLUMN courier_uuid SET NOT NULL;
+
+UPDATE orders SET status = 'CANCELED' WHERE status = 'EXPIRED';
+UPDATE order_status_history SET from_status = 'CANCELED' WHERE from_status = 'EXPIRED';
+UPDATE order_status_history SET to_status = 'CANCELED' WHERE to_status = 'EXPIRED';
+
+ALTER TYPE order_status RENAME TO order_status_old;
+CREATE TYPE order_status AS ENUM (
+    'WAITING_FOR_COURIER',
+    'ASSIGNED',
+    'PICKED_UP',
+    'IN_PROGRESS',
+    'FINISHED',
+    'CANCELED'
+);
+ALTER TABLE orders
+    ALTER COLUMN status DROP DEFAULT,
+    ALTER COLUMN status TYPE order_status USING status::TEXT::order_status,
+    ALTER COLUMN status SET DEFAULT 'ASSIGNED';
+ALTER TABLE order_status_history
+    ALTER COLUMN from_status TYPE order_status USING from_status::TEXT::order_status,
+    ALTER COLUMN to_status TYPE order_status USING to_status::TEXT::order_status;
+DROP TYPE order_status_old;
@@ -0,0 +1,12 @@
+-- Order of queued user stays without courier until one is found
+ALTER TYPE order_status ADD VALUE 'EXPIRED';
+
+ALTER TABLE orders
+    ALTER COLUMN courier_uuid DROP NOT NULL,
+    ADD COLUMN restaurant_uuid UUID,
+    ADD CONSTRAINT FK_RESTAURANT
+        FOREIGN KEY(restaurant_uuid)
+            REFERENCES restaurant(uuid);
+
+CREATE INDEX idx_orders_waiting_for_courier ON orders (user_uuid, created_at)
+    WHERE courier_uuid IS NULL;
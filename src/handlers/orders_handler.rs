@@ -1,16 +1,29 @@
 use crate::models::orders_model::{
-    BucketItem, CreateOrder, OrderInfo, OrderItem, OrderQueueInfo, UpdateProduct,
-};
-use crate::repository::orders_repository::{
-    delete_items_from_user_bucket, move_from_bucket_to_order, update_order_rating,
+    BucketItem, BucketSummary, Category, CategoryNode, Checkout, CreateCategory, CreateCheckout,
+    CreateModifierGroup, CreateModifierOption, CreateOpeningHours, CreateRestaurant, FlatCategory,
+    Holiday, HolidayInput, ModifierGroup, ModifierOption, Money, OpeningHours, OpeningHoursInput,
+    OrderConnection, OrderCursor, OrderFilter, OrderInfo, OrderItem, OrderQueueInfo, OrderStatus,
+    OrderStatusEvent, ProductConnection, ProductCursor, ProductFilter, ReorderReport, Restaurant,
+    RestaurantInput, UpdateCategory, UpdateProduct, UpdateRestaurant, UpdateRestaurantInput,
 };
+use crate::repository::orders_repository::update_order_rating;
 use crate::resources::postgresql::execute_connection;
-use crate::services::orders_service::{check_bucket, check_time_expiration};
+use crate::services::analytics_service::report_finished_order;
+use crate::services::orders_service::{
+    self, build_category_tree, category_with_descendants, change_order_status, check_bucket,
+    check_cancellation_time, check_time_expiration, create_checkout_from_bucket,
+    find_checkout_by_idempotency_key, flatten_category_tree, save_idempotency_key,
+    select_valid_options, validate_restaurant_schedule, with_descendant_categories,
+};
 use crate::services::users_service::{
-    check_courier_from_queue, find_free_courier, update_courier_rating,
+    check_courier_from_queue, find_free_courier, notify_courier_about_cancellation,
+    release_courier, update_courier_rating,
 };
+use crate::utils::configs::Config;
 use crate::utils::graphql_utils::{
-    has_access, has_access_by_uuid, has_access_to_filters, has_access_to_order, policy_from_context,
+    build_connection, has_access, has_access_by_uuid, has_access_to_filters, has_access_to_order,
+    idempotency_key_from_context, maybe_undefined_to_option, page_from_args, policy_from_context,
+    token_claims_from_context, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
 };
 use crate::{
     models::orders_model::{CreateProduct, ProductInfo},
@@ -18,11 +31,18 @@ use crate::{
     schema::graphql_schema::{MutationRoot, QueryRoot, SubscriptionRoot},
 };
 
-use async_graphql::{Context, FieldResult, Schema};
+use async_graphql::connection::query;
+use async_graphql::{Context, Error, FieldResult, MaybeUndefined, Schema};
+use chrono::Utc;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use tracing::info;
 use uuid::Uuid;
 
 pub type OrderServiceSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 pub struct Products;
+pub struct Categories;
+pub struct Restaurants;
 pub struct Buckets;
 pub struct Orders;
 
@@ -37,23 +57,55 @@ impl Products {
     pub async fn products(
         &self,
         context: &Context<'_>,
-        name: Option<String>,
-        price_from_cheap: Option<bool>,
-        price_from_expensive: Option<bool>,
-        product_type: Option<String>,
-        restaurant: Option<String>,
-    ) -> FieldResult<Vec<ProductInfo>> {
+        filter: ProductFilter,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<ProductConnection> {
         let mut db_conn = execute_connection(context).await?;
+        let filter = with_descendant_categories(&mut db_conn, filter).await?;
 
-        let products = orders_repository::select_products_by_filter(
-            &mut db_conn,
-            name,
-            price_from_cheap,
-            price_from_expensive,
-            product_type,
-            restaurant,
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = page_from_args(after, before, first, last);
+                let total_count = orders_repository::count_products(&mut db_conn, &filter).await?;
+                let products =
+                    orders_repository::select_products_page(&mut db_conn, &filter, &page).await?;
+                Ok::<_, Error>(build_connection(
+                    products,
+                    &page,
+                    total_count,
+                    |product: &ProductInfo| ProductCursor::from(product),
+                ))
+            },
         )
-        .await?;
+        .await
+    }
+
+    pub async fn search_products(
+        &self,
+        context: &Context<'_>,
+        query: String,
+        first: Option<i32>,
+    ) -> FieldResult<Vec<ProductInfo>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err("Search query must not be empty".into());
+        }
+        let limit = match first {
+            Some(first) if first < 0 => return Err("\"first\" must not be negative".into()),
+            Some(first) => (first as usize).min(MAX_PAGE_SIZE),
+            None => DEFAULT_PAGE_SIZE,
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        let products =
+            orders_repository::search_products(&mut db_conn, query, limit as i64).await?;
         Ok(products)
     }
 
@@ -61,46 +113,304 @@ impl Products {
         &self,
         context: &Context<'_>,
         name: Option<String>,
-        price: Option<f64>,
-        product_type: Option<String>,
-        restaurant: Option<String>,
+        price: Option<Money>,
+        category_uuid: Option<Uuid>,
+        restaurant_uuid: Option<Uuid>,
         uuid: Uuid,
     ) -> FieldResult<ProductInfo> {
         let policy = policy_from_context(context)?;
         if !has_access(&policy.admin_policy, context) {
             return Err("Forbidden".into());
         };
+        if price.is_some_and(|price| price < Money::default()) {
+            return Err("Price cannot be negative".into());
+        }
         let mut db_conn = execute_connection(context).await?;
 
         let product = UpdateProduct {
             name,
             price,
-            product_type,
-            restaurant,
+            category_uuid,
+            restaurant_uuid,
         };
         let product = orders_repository::update_product(&mut db_conn, product, uuid).await?;
         Ok(product)
     }
 
+    // Restaurant staff can manage stock of their own restaurant only.
+    // "delta" changes tracked stock, "stock" sets it, null stops tracking
+    pub async fn adjust_stock(
+        &self,
+        context: &Context<'_>,
+        product_uuid: Uuid,
+        delta: Option<i32>,
+        stock: MaybeUndefined<i32>,
+        is_available: Option<bool>,
+    ) -> FieldResult<ProductInfo> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.restaurant_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        let product = orders_repository::select_product(&mut db_conn, product_uuid).await?;
+        if !has_access(&policy.admin_policy, context) {
+            let restaurant =
+                orders_repository::select_restaurant(&mut db_conn, product.restaurant_uuid).await?;
+            let claims = token_claims_from_context(context);
+            if restaurant.account_uuid != Some(claims.uuid) {
+                return Err("Forbidden".into());
+            }
+        }
+
+        let new_stock = match (delta, stock) {
+            (Some(_), MaybeUndefined::Null | MaybeUndefined::Value(_)) => {
+                return Err("Either \"delta\" or \"stock\" can be set".into())
+            }
+            (Some(delta), MaybeUndefined::Undefined) => Some(
+                product
+                    .stock
+                    .unwrap_or_default()
+                    .checked_add(delta)
+                    .ok_or("Stock is out of range")?,
+            ),
+            (None, MaybeUndefined::Value(stock)) => Some(stock),
+            (None, MaybeUndefined::Null) => None,
+            (None, MaybeUndefined::Undefined) => product.stock,
+        };
+        if new_stock.is_some_and(|stock| stock < 0) {
+            return Err("Stock cannot be negative".into());
+        }
+        let is_available = is_available.unwrap_or(product.is_available);
+
+        orders_repository::update_product_stock(
+            &mut db_conn,
+            product_uuid,
+            product.stock,
+            new_stock,
+            is_available,
+        )
+        .await?
+        .ok_or_else(|| "Stock was changed concurrently, please retry".into())
+    }
+
+    // Archived product is hidden from listings and removed from buckets,
+    // but it still resolves for order history
+    pub async fn archive_product(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<ProductInfo> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let product = orders_repository::archive_product(conn, uuid)
+                        .await?
+                        .ok_or("Product is already archived")?;
+                    orders_repository::delete_product_from_buckets(conn, uuid).await?;
+                    Ok(product)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    pub async fn restore_product(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<ProductInfo> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        let product = orders_repository::restore_product(&mut db_conn, uuid)
+            .await?
+            .ok_or("Product is not archived")?;
+        Ok(product)
+    }
+
+    pub async fn modifier_groups(
+        &self,
+        context: &Context<'_>,
+        product_uuid: Uuid,
+    ) -> FieldResult<Vec<ModifierGroup>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let groups = orders_repository::select_modifier_groups(&mut db_conn, product_uuid).await?;
+        Ok(groups)
+    }
+
+    pub async fn modifier_options(
+        &self,
+        context: &Context<'_>,
+        group_uuid: Uuid,
+    ) -> FieldResult<Vec<ModifierOption>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let options = orders_repository::select_modifier_options(&mut db_conn, group_uuid).await?;
+        Ok(options)
+    }
+
+    pub async fn modifier_options_by_uuids(
+        &self,
+        context: &Context<'_>,
+        option_uuids: Vec<Uuid>,
+    ) -> FieldResult<Vec<ModifierOption>> {
+        if option_uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut db_conn = execute_connection(context).await?;
+
+        let options =
+            orders_repository::select_modifier_options_by_uuids(&mut db_conn, option_uuids).await?;
+        Ok(options)
+    }
+
+    pub async fn create_modifier_group(
+        &self,
+        context: &Context<'_>,
+        product_uuid: Uuid,
+        name: String,
+        min_select: i32,
+        max_select: i32,
+        position: i32,
+    ) -> FieldResult<ModifierGroup> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        if min_select < 0 || max_select < 1 || max_select < min_select {
+            return Err("Invalid selection limits".into());
+        }
+        let mut db_conn = execute_connection(context).await?;
+
+        let new_group = CreateModifierGroup {
+            product_uuid,
+            name,
+            min_select,
+            max_select,
+            position,
+        };
+        let group = orders_repository::create_modifier_group(&mut db_conn, new_group).await?;
+        Ok(group)
+    }
+
+    pub async fn create_modifier_option(
+        &self,
+        context: &Context<'_>,
+        group_uuid: Uuid,
+        name: String,
+        price_delta: Money,
+        position: i32,
+    ) -> FieldResult<ModifierOption> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        let new_option = CreateModifierOption {
+            group_uuid,
+            name,
+            price_delta,
+            position,
+        };
+        let option = orders_repository::create_modifier_option(&mut db_conn, new_option).await?;
+        Ok(option)
+    }
+
+    // Bucket items with removed options are removed as well
+    pub async fn delete_modifier_group(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<String> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let options = orders_repository::select_modifier_options(conn, uuid).await?;
+                    let option_uuids = options.iter().map(|option| option.uuid).collect();
+                    orders_repository::delete_bucket_items_with_options(conn, option_uuids).await?;
+                    if orders_repository::delete_modifier_group(conn, uuid).await? == 0 {
+                        return Err("Modifier group not found".into());
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+        Ok("Modifier group deleted".into())
+    }
+
+    pub async fn delete_modifier_option(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<String> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let mut db_conn = execute_connection(context).await?;
+
+        db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    orders_repository::delete_bucket_items_with_options(conn, vec![uuid]).await?;
+                    if orders_repository::delete_modifier_option(conn, uuid).await? == 0 {
+                        return Err("Modifier option not found".into());
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+        Ok("Modifier option deleted".into())
+    }
+
     pub async fn create_product(
         &self,
         context: &Context<'_>,
         name: String,
-        price: f64,
-        product_type: String,
-        restaurant: String,
+        price: Money,
+        category_uuid: Uuid,
+        restaurant_uuid: Uuid,
     ) -> FieldResult<String> {
         let policy = policy_from_context(context)?;
         if !has_access(&policy.admin_policy, context) {
             return Err("Forbidden".into());
         };
+        if price < Money::default() {
+            return Err("Price cannot be negative".into());
+        }
         let mut db_conn = execute_connection(context).await?;
 
+        let currency = context
+            .data::<Config>()
+            .expect("Cannot parse AppState from context")
+            .currency
+            .clone();
         let product = CreateProduct {
             name,
             price,
-            product_type,
-            restaurant,
+            category_uuid,
+            restaurant_uuid,
+            currency,
         };
         orders_repository::create_product(&mut db_conn, product).await?;
 
@@ -108,6 +418,284 @@ impl Products {
     }
 }
 
+impl Categories {
+    pub async fn category(&self, context: &Context<'_>, uuid: Uuid) -> FieldResult<Category> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let category = orders_repository::select_category(&mut db_conn, uuid).await?;
+        Ok(category)
+    }
+
+    pub async fn categories(&self, context: &Context<'_>) -> FieldResult<Vec<CategoryNode>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let categories = orders_repository::select_categories(&mut db_conn).await?;
+        Ok(build_category_tree(&categories, None))
+    }
+
+    pub async fn category_list(&self, context: &Context<'_>) -> FieldResult<Vec<FlatCategory>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let categories = orders_repository::select_categories(&mut db_conn).await?;
+        Ok(flatten_category_tree(&categories))
+    }
+
+    pub async fn create_category(
+        &self,
+        context: &Context<'_>,
+        name: String,
+        display_name: String,
+        parent_uuid: Option<Uuid>,
+        position: i32,
+    ) -> FieldResult<Category> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Err("Category name cannot be empty".into());
+        }
+        let mut db_conn = execute_connection(context).await?;
+
+        let new_category = CreateCategory {
+            parent_uuid,
+            name,
+            display_name,
+            position,
+        };
+        let category = orders_repository::create_category(&mut db_conn, new_category).await?;
+        Ok(category)
+    }
+
+    pub async fn update_category(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+        name: Option<String>,
+        display_name: Option<String>,
+        parent_uuid: MaybeUndefined<Uuid>,
+        position: Option<i32>,
+    ) -> FieldResult<Category> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let name = name.map(|name| name.trim().to_lowercase());
+        if name.as_ref().is_some_and(|name| name.is_empty()) {
+            return Err("Category name cannot be empty".into());
+        }
+        let mut db_conn = execute_connection(context).await?;
+
+        let parent_uuid = maybe_undefined_to_option(parent_uuid);
+        if let Some(Some(parent_uuid)) = parent_uuid {
+            let categories = orders_repository::select_categories(&mut db_conn).await?;
+            if category_with_descendants(&categories, uuid).contains(&parent_uuid) {
+                return Err("Category cannot be nested into itself".into());
+            }
+        }
+        let changes = UpdateCategory {
+            parent_uuid,
+            name,
+            display_name,
+            position,
+            updated_at: Utc::now().naive_utc(),
+        };
+        let category = orders_repository::update_category(&mut db_conn, uuid, changes).await?;
+        Ok(category)
+    }
+}
+
+impl Restaurants {
+    pub async fn restaurant(&self, context: &Context<'_>, uuid: Uuid) -> FieldResult<Restaurant> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let restaurant = orders_repository::select_restaurant(&mut db_conn, uuid).await?;
+        Ok(restaurant)
+    }
+
+    pub async fn restaurants(&self, context: &Context<'_>) -> FieldResult<Vec<Restaurant>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let restaurants = orders_repository::select_restaurants(&mut db_conn).await?;
+        Ok(restaurants)
+    }
+
+    pub async fn opening_hours(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<Vec<OpeningHours>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let hours = orders_repository::select_opening_hours(&mut db_conn, uuid).await?;
+        Ok(hours)
+    }
+
+    pub async fn holidays(&self, context: &Context<'_>, uuid: Uuid) -> FieldResult<Vec<Holiday>> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let holidays = orders_repository::select_holidays(&mut db_conn, uuid).await?;
+        Ok(holidays)
+    }
+
+    pub async fn create_restaurant(
+        &self,
+        context: &Context<'_>,
+        input: RestaurantInput,
+    ) -> FieldResult<Restaurant> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let name = input.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Restaurant name cannot be empty".into());
+        }
+        validate_restaurant_schedule(
+            Some(&input.time_zone),
+            Some(&input.opening_hours),
+            Some(&input.holidays),
+        )?;
+        let mut db_conn = execute_connection(context).await?;
+
+        let new_restaurant = CreateRestaurant {
+            name,
+            address: input.address,
+            phone: input.phone,
+            email: input.email,
+            time_zone: input.time_zone,
+            account_uuid: input.account_uuid,
+        };
+        let (opening_hours, holidays) = (input.opening_hours, input.holidays);
+        let restaurant = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let restaurant =
+                        orders_repository::create_restaurant(conn, new_restaurant).await?;
+                    replace_schedule(conn, restaurant.uuid, Some(opening_hours), Some(holidays))
+                        .await?;
+                    Ok(restaurant)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        Ok(restaurant)
+    }
+
+    pub async fn update_restaurant(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+        input: UpdateRestaurantInput,
+    ) -> FieldResult<Restaurant> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            return Err("Forbidden".into());
+        };
+        let name = input.name.map(|name| name.trim().to_string());
+        if name.as_ref().is_some_and(|name| name.is_empty()) {
+            return Err("Restaurant name cannot be empty".into());
+        }
+        validate_restaurant_schedule(
+            input.time_zone.as_deref(),
+            input.opening_hours.as_deref(),
+            input.holidays.as_deref(),
+        )?;
+        let mut db_conn = execute_connection(context).await?;
+
+        let changes = UpdateRestaurant {
+            name,
+            address: input.address,
+            phone: input.phone,
+            email: input.email,
+            time_zone: input.time_zone,
+            account_uuid: maybe_undefined_to_option(input.account_uuid),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let (opening_hours, holidays) = (input.opening_hours, input.holidays);
+        let restaurant = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let restaurant =
+                        orders_repository::update_restaurant(conn, uuid, changes).await?;
+                    replace_schedule(conn, uuid, opening_hours, holidays).await?;
+                    Ok(restaurant)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        Ok(restaurant)
+    }
+}
+
+// Returns couriers reserved for orders which were not created
+async fn release_couriers(
+    context: &Context<'_>,
+    couriers: &[(Uuid, Option<Uuid>)],
+    user_uuid: Uuid,
+) {
+    let users_channel = &context
+        .data::<Config>()
+        .expect("Cannot parse AppState from context")
+        .users_channel;
+    for courier_uuid in couriers
+        .iter()
+        .filter_map(|(_, courier_uuid)| courier_uuid.as_ref())
+    {
+        if let Err(error) = release_courier(users_channel.clone(), *courier_uuid, user_uuid).await {
+            tracing::error!(
+                courier_uuid = %courier_uuid,
+                user_uuid = %user_uuid,
+                error.message = ?error.message,
+                "Cannot release courier after failed order creation"
+            );
+        }
+    }
+}
+
+fn max_bucket_item_amount(context: &Context<'_>) -> i16 {
+    context
+        .data::<Config>()
+        .expect("Cannot parse AppState from context")
+        .max_bucket_item_amount
+}
+
+// Replaces opening hours and holidays that are set
+async fn replace_schedule(
+    db_conn: &mut AsyncPgConnection,
+    restaurant_uuid: Uuid,
+    opening_hours: Option<Vec<OpeningHoursInput>>,
+    holidays: Option<Vec<HolidayInput>>,
+) -> Result<(), Error> {
+    if let Some(opening_hours) = opening_hours {
+        let opening_hours = opening_hours
+            .into_iter()
+            .map(|hours| CreateOpeningHours {
+                restaurant_uuid,
+                weekday: hours.weekday,
+                opens_at: hours.opens_at,
+                closes_at: hours.closes_at,
+            })
+            .collect();
+        orders_repository::replace_opening_hours(db_conn, restaurant_uuid, opening_hours).await?;
+    }
+    if let Some(holidays) = holidays {
+        let holidays = holidays
+            .into_iter()
+            .map(|holiday| Holiday {
+                restaurant_uuid,
+                date: holiday.date,
+                opens_at: holiday.opens_at,
+                closes_at: holiday.closes_at,
+                note: holiday.note,
+            })
+            .collect();
+        orders_repository::replace_holidays(db_conn, restaurant_uuid, holidays).await?;
+    }
+    Ok(())
+}
+
 impl Buckets {
     pub async fn add_to_bucket(
         &self,
@@ -115,6 +703,7 @@ impl Buckets {
         user_uuid: Uuid,
         product_uuid: Uuid,
         amount: i16,
+        option_uuids: Vec<Uuid>,
     ) -> FieldResult<BucketItem> {
         // let policy = policy_from_context(context)?;
         // if !has_access(&policy.admin_policy, context) {
@@ -122,15 +711,82 @@ impl Buckets {
         //     return Err("Forbidden".into())
         // };
         has_access_by_uuid(context, user_uuid).await?;
+        let max_amount = max_bucket_item_amount(context);
+        if amount <= 0 || amount > max_amount {
+            return Err(format!("Amount must be from 1 to {}", max_amount).into());
+        }
         let mut db_conn = execute_connection(context).await?;
 
+        let product = orders_repository::select_product(&mut db_conn, product_uuid).await?;
+        if product.archived_at.is_some() {
+            return Err("Product is archived".into());
+        }
+        select_valid_options(&mut db_conn, product_uuid, &option_uuids).await?;
+        let mut option_uuids = option_uuids;
+        option_uuids.sort();
         let bucket_item = BucketItem {
             user_uuid,
             product_uuid,
             amount,
+            option_uuids,
         };
-        let bucket_item = orders_repository::create_bucket_item(&mut db_conn, bucket_item).await?;
-        Ok(bucket_item)
+        db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let bucket_item =
+                        orders_repository::upsert_bucket_item(conn, bucket_item).await?;
+                    if bucket_item.amount > max_amount {
+                        return Err(format!(
+                            "Bucket can contain up to {} items of the same product",
+                            max_amount
+                        )
+                        .into());
+                    }
+                    Ok(bucket_item)
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+
+    // Sets absolute amount of bucket line, line is removed when amount is 0
+    pub async fn update_bucket_item_amount(
+        &self,
+        context: &Context<'_>,
+        user_uuid: Uuid,
+        product_uuid: Uuid,
+        option_uuids: Vec<Uuid>,
+        amount: i16,
+    ) -> FieldResult<Option<BucketItem>> {
+        has_access_by_uuid(context, user_uuid).await?;
+        let max_amount = max_bucket_item_amount(context);
+        if amount < 0 || amount > max_amount {
+            return Err(format!("Amount must be from 0 to {}", max_amount).into());
+        }
+        let mut db_conn = execute_connection(context).await?;
+
+        let mut option_uuids = option_uuids;
+        option_uuids.sort();
+        if amount == 0 {
+            orders_repository::delete_item_from_bucket(
+                &mut db_conn,
+                user_uuid,
+                product_uuid,
+                Some(option_uuids),
+            )
+            .await?;
+            return Ok(None);
+        }
+        let bucket_item = orders_repository::update_bucket_item_amount(
+            &mut db_conn,
+            user_uuid,
+            product_uuid,
+            option_uuids,
+            amount,
+        )
+        .await?
+        .ok_or("Item not found in bucket")?;
+        Ok(Some(bucket_item))
     }
 
     pub async fn get_bucket_items(
@@ -148,11 +804,31 @@ impl Buckets {
         Ok(items)
     }
 
+    // Bucket with current prices and warnings that checkout would fail on
+    pub async fn bucket_summary(
+        &self,
+        context: &Context<'_>,
+        user_uuid: Uuid,
+    ) -> FieldResult<BucketSummary> {
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.admin_policy, context) {
+            has_access_by_uuid(context, user_uuid).await?;
+        };
+        let config = context
+            .data::<Config>()
+            .expect("Cannot parse AppState from context");
+
+        let mut db_conn = execute_connection(context).await?;
+        let summary = orders_service::summarize_bucket(&mut db_conn, config, user_uuid).await?;
+        Ok(summary)
+    }
+
     pub async fn remove_from_bucket(
         &self,
         context: &Context<'_>,
         user_uuid: Uuid,
         product_uuid: Uuid,
+        option_uuids: Option<Vec<Uuid>>,
     ) -> FieldResult<String> {
         // let policy = policy_from_context(context)?;
         // if !has_access(&policy.admin_policy, context) {
@@ -161,7 +837,17 @@ impl Buckets {
         has_access_by_uuid(context, user_uuid).await?;
         let mut db_conn = execute_connection(context).await?;
 
-        orders_repository::delete_item_from_bucket(&mut db_conn, user_uuid, product_uuid).await?;
+        let option_uuids = option_uuids.map(|mut option_uuids| {
+            option_uuids.sort();
+            option_uuids
+        });
+        orders_repository::delete_item_from_bucket(
+            &mut db_conn,
+            user_uuid,
+            product_uuid,
+            option_uuids,
+        )
+        .await?;
         Ok("Item deleted".to_string())
     }
 
@@ -183,47 +869,195 @@ impl Buckets {
 }
 
 impl Orders {
+    // Kept for clients ordering from a single restaurant,
+    // bucket with several restaurants has to be ordered with create_checkout
     pub async fn create_order(
         &self,
         context: &Context<'_>,
         user_uuid: Uuid,
         address: String,
+        idempotency_key: Option<String>,
     ) -> FieldResult<OrderInfo> {
         has_access_by_uuid(context, user_uuid).await?;
         let mut db_conn = execute_connection(context).await?;
 
-        println!("checking bucket");
-        let bucket = check_bucket(&mut db_conn, user_uuid).await?;
-        println!("bucket checked");
-        println!("searching for new courier");
-        // searching for a free courier or adding user in queue
+        let restaurants =
+            orders_repository::select_bucket_restaurants(&mut db_conn, user_uuid).await?;
+        if restaurants.len() > 1 {
+            return Err(
+                "Products from different restaurants have to be ordered with createCheckout".into(),
+            );
+        }
+        let checkout = self
+            .create_checkout(context, user_uuid, address, idempotency_key)
+            .await?;
+        orders_repository::select_checkout_orders(&mut db_conn, checkout.uuid)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Checkout has no orders".into())
+    }
+
+    // Repeated request with the same idempotency key
+    // returns already created checkout instead of creating a new one.
+    // Bucket is split into one order per restaurant, each with its own courier.
+    // If user was added in queue, order waits for courier with captured items,
+    // their stock is reserved when the courier is found
+    pub async fn create_checkout(
+        &self,
+        context: &Context<'_>,
+        user_uuid: Uuid,
+        address: String,
+        idempotency_key: Option<String>,
+    ) -> FieldResult<Checkout> {
+        has_access_by_uuid(context, user_uuid).await?;
+        let idempotency_key = idempotency_key_from_context(context, idempotency_key)?;
+        let mut db_conn = execute_connection(context).await?;
+
+        if let Some(key) = &idempotency_key {
+            if let Some(checkout) =
+                find_checkout_by_idempotency_key(&mut db_conn, user_uuid, key).await?
+            {
+                return Ok(checkout);
+            }
+        }
+
+        let config = context
+            .data::<Config>()
+            .expect("Cannot parse AppState from context");
+        check_bucket(&mut db_conn, config, user_uuid).await?;
+        let restaurants =
+            orders_repository::select_bucket_restaurants(&mut db_conn, user_uuid).await?;
+        if config.forbid_mixed_restaurants && restaurants.len() > 1 {
+            return Err("Products from different restaurants cannot be ordered together".into());
+        }
+        // searching for a free courier for every restaurant or adding user in queue
         // if there are no free couriers
-        let courier_uuid = find_free_courier(context, user_uuid).await?;
-        println!("couriers finded");
-        let order = CreateOrder {
+        let mut couriers = Vec::with_capacity(restaurants.len());
+        for restaurant in &restaurants {
+            match find_free_courier(config.users_channel.clone(), user_uuid).await {
+                Ok(courier_uuid) => couriers.push((restaurant.uuid, courier_uuid)),
+                Err(error) => {
+                    release_couriers(context, &couriers, user_uuid).await;
+                    return Err(error);
+                }
+            }
+        }
+
+        let idempotency_key_ttl = config.idempotency_key_ttl;
+        let delivery_fee = config.delivery_fee;
+        let checkout = CreateCheckout {
             user_uuid,
-            courier_uuid,
             address,
+            currency: config.currency.clone(),
         };
-        println!("creating order");
-        let order = orders_repository::create_order(&mut db_conn, order).await?;
-        println!("order created");
-        let order_items = bucket
-            .iter()
-            .map(|item| OrderItem {
-                order_uuid: order.uuid,
-                product_uuid: item.product_uuid,
-                amount: item.amount,
+        let key = idempotency_key.clone();
+        let reserved = couriers.clone();
+        let result = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    let (checkout, orders) =
+                        create_checkout_from_bucket(conn, checkout, &reserved, delivery_fee)
+                            .await?;
+                    if let Some(key) = key {
+                        save_idempotency_key(
+                            conn,
+                            user_uuid,
+                            key,
+                            checkout.uuid,
+                            idempotency_key_ttl,
+                        )
+                        .await?;
+                    }
+                    Ok((checkout, orders))
+                }
+                .scope_boxed()
             })
-            .collect::<Vec<OrderItem>>();
-        println!("moving items from bucket to order");
-        move_from_bucket_to_order(&mut db_conn, order_items).await?;
-        println!("items from bucket moved");
-        println!("deleting items from bucket");
-        delete_items_from_user_bucket(&mut db_conn, user_uuid).await?;
-        println!("items from bucket deleted");
+            .await;
 
-        Ok(order)
+        match result {
+            Ok((checkout, orders)) => {
+                for order in &orders {
+                    info!(
+                        order_uuid = %order.uuid,
+                        checkout_uuid = %checkout.uuid,
+                        user_uuid = %user_uuid,
+                        "Order created"
+                    );
+                }
+                Ok(checkout)
+            }
+            Err(error) => {
+                // Couriers were already reserved for this user,
+                // they have to be released because orders were not created
+                release_couriers(context, &couriers, user_uuid).await;
+                // Concurrent request with the same idempotency key
+                // could create checkout in the meantime
+                if let Some(key) = &idempotency_key {
+                    if let Some(checkout) =
+                        find_checkout_by_idempotency_key(&mut db_conn, user_uuid, key).await?
+                    {
+                        return Ok(checkout);
+                    }
+                }
+                Err(error)
+            }
+        }
+    }
+
+    pub async fn checkout(&self, context: &Context<'_>, uuid: Uuid) -> FieldResult<Checkout> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let checkout = orders_repository::select_checkout(&mut db_conn, uuid).await?;
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.analyst_policy, context) {
+            has_access_by_uuid(context, checkout.user_uuid).await?;
+        }
+        Ok(checkout)
+    }
+
+    // Orders of checkout are visible to the user who created it
+    pub async fn get_checkout_orders(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<Vec<OrderInfo>> {
+        let mut db_conn = execute_connection(context).await?;
+        let checkout = orders_repository::select_checkout(&mut db_conn, uuid).await?;
+
+        let policy = policy_from_context(context)?;
+        if !has_access(&policy.analyst_policy, context) {
+            has_access_by_uuid(context, checkout.user_uuid).await?;
+        }
+        let orders = orders_repository::select_checkout_orders(&mut db_conn, uuid).await?;
+        Ok(orders)
+    }
+
+    // Copies items of finished order back into user's bucket
+    pub async fn reorder(
+        &self,
+        context: &Context<'_>,
+        order_uuid: Uuid,
+    ) -> FieldResult<ReorderReport> {
+        let mut db_conn = execute_connection(context).await?;
+
+        let order = orders_repository::select_order(&mut db_conn, order_uuid).await?;
+        let policy = policy_from_context(context)?;
+        has_access_to_order(&policy.analyst_policy, context, order.clone()).await?;
+        // items are written into bucket of order's user,
+        // so couriers and analysts who can read the order cannot reorder it
+        if token_claims_from_context(context).uuid != order.user_uuid {
+            return Err("Forbidden".into());
+        }
+
+        let max_amount = max_bucket_item_amount(context);
+        let report = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move { orders_service::reorder_into_bucket(conn, &order, max_amount).await }
+                    .scope_boxed()
+            })
+            .await?;
+        Ok(report)
     }
 
     pub async fn order(&self, context: &Context<'_>, uuid: Uuid) -> FieldResult<OrderInfo> {
@@ -243,22 +1077,34 @@ impl Orders {
     pub async fn filter_orders(
         &self,
         context: &Context<'_>,
-        order_uuid: Option<Uuid>,
-        courier_uuid: Option<Uuid>,
-        user_uuid: Option<Uuid>,
-        address: Option<String>,
-    ) -> FieldResult<Vec<OrderInfo>> {
-        has_access_to_filters(context, courier_uuid, user_uuid).await?;
+        filter: OrderFilter,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<OrderConnection> {
+        has_access_to_filters(context, filter.courier_uuid, filter.user_uuid).await?;
         let mut db_conn = execute_connection(context).await?;
-        let orders = orders_repository::select_orders_by_filters(
-            &mut db_conn,
-            order_uuid,
-            courier_uuid,
-            user_uuid,
-            address,
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = page_from_args(after, before, first, last);
+                let total_count = orders_repository::count_orders(&mut db_conn, &filter).await?;
+                let orders =
+                    orders_repository::select_orders_page(&mut db_conn, &filter, &page).await?;
+                Ok::<_, Error>(build_connection(
+                    orders,
+                    &page,
+                    total_count,
+                    |order: &OrderInfo| OrderCursor::from(order),
+                ))
+            },
         )
-        .await?;
-        Ok(orders)
+        .await
     }
 
     pub async fn get_order_items(
@@ -275,6 +1121,35 @@ impl Orders {
         Ok(items)
     }
 
+    // Sum of order items prices captured at order creation
+    pub async fn get_order_subtotal(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<Money> {
+        let items = self.get_order_items(context, uuid).await?;
+        let line_prices = items
+            .iter()
+            .map(|item| item.unit_price.times(item.amount))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Money::total(line_prices)?)
+    }
+
+    // Status changes of order, ordered by time
+    pub async fn get_order_timeline(
+        &self,
+        context: &Context<'_>,
+        uuid: Uuid,
+    ) -> FieldResult<Vec<OrderStatusEvent>> {
+        let mut db_conn = execute_connection(context).await?;
+        let order = orders_repository::select_order(&mut db_conn, uuid).await?;
+
+        let policy = policy_from_context(context)?;
+        has_access_to_order(&policy.analyst_policy, context, order).await?;
+        let events = orders_repository::select_order_status_events(&mut db_conn, uuid).await?;
+        Ok(events)
+    }
+
     pub async fn estimate_delivery(
         &self,
         context: &Context<'_>,
@@ -288,8 +1163,9 @@ impl Orders {
             None => {
                 has_access_by_uuid(context, order.user_uuid).await?;
                 check_time_expiration(context, &order).await?;
+                let courier_uuid = order.courier_uuid.ok_or("Order has no courier")?;
                 update_order_rating(&mut db_conn, order.uuid, rating).await?;
-                update_courier_rating(&mut db_conn, context, order.courier_uuid).await
+                update_courier_rating(&mut db_conn, context, courier_uuid).await
             }
         }
     }
@@ -301,16 +1177,90 @@ impl Orders {
     ) -> FieldResult<String> {
         let mut db_conn = execute_connection(context).await?;
         let order = orders_repository::select_order(&mut db_conn, order_uuid).await?;
-        match order.status.as_str() {
-            "IN_PROGRESS" => {
-                has_access_by_uuid(context, order.courier_uuid).await?;
-                let new_status = "FINISHED";
-                orders_repository::update_order_status(&mut db_conn, order_uuid, new_status)
-                    .await?;
-                Ok("Delivery finished".to_string())
+        let courier_uuid = order.courier_uuid.ok_or("Forbidden")?;
+        has_access_by_uuid(context, courier_uuid).await?;
+        change_order_status(
+            &mut db_conn,
+            &order,
+            OrderStatus::Finished,
+            Some(courier_uuid),
+        )
+        .await?;
+
+        let config = context
+            .data::<Config>()
+            .expect("Cannot parse AppState from context");
+        report_finished_order(config, order_uuid, Utc::now());
+        Ok("Delivery finished".to_string())
+    }
+
+    // Couriers move their orders through delivery stages
+    // Finishing and cancellation are available through their own mutations
+    pub async fn update_order_status(
+        &self,
+        context: &Context<'_>,
+        order_uuid: Uuid,
+        status: OrderStatus,
+    ) -> FieldResult<OrderInfo> {
+        if !matches!(status, OrderStatus::PickedUp | OrderStatus::InProgress) {
+            return Err("This status cannot be set directly".into());
+        }
+        let mut db_conn = execute_connection(context).await?;
+        let order = orders_repository::select_order(&mut db_conn, order_uuid).await?;
+        let courier_uuid = order.courier_uuid.ok_or("Forbidden")?;
+        has_access_by_uuid(context, courier_uuid).await?;
+        change_order_status(&mut db_conn, &order, status, Some(courier_uuid)).await?;
+
+        let order = orders_repository::select_order(&mut db_conn, order_uuid).await?;
+        Ok(order)
+    }
+
+    // Users can cancel their own orders within the cancellation window,
+    // admins can cancel any order at any time.
+    // Assigned courier is notified through users service
+    pub async fn cancel_order(
+        &self,
+        context: &Context<'_>,
+        order_uuid: Uuid,
+        reason: String,
+    ) -> FieldResult<String> {
+        let mut db_conn = execute_connection(context).await?;
+        let order = orders_repository::select_order(&mut db_conn, order_uuid).await?;
+
+        // ownership is checked first, so errors below don't reveal
+        // anything about orders of other users
+        let policy = policy_from_context(context)?;
+        let is_admin = has_access(&policy.admin_policy, context);
+        if !is_admin {
+            has_access_by_uuid(context, order.user_uuid).await?;
+        }
+        let canceled_by = token_claims_from_context(context).uuid;
+        // retried request succeeds instead of failing with illegal transition
+        if order.status == OrderStatus::Canceled && order.canceled_by == Some(canceled_by) {
+            return Ok("Order canceled".to_string());
+        }
+        let reason = reason.trim().to_string();
+        if reason.is_empty() {
+            return Err("Cancellation reason required".into());
+        }
+        if !is_admin {
+            check_cancellation_time(context, &order)?;
+        }
+
+        orders_service::cancel_order(&mut db_conn, &order, canceled_by, reason.clone()).await?;
+
+        // order waiting for courier has nobody to notify
+        if let Some(courier_uuid) = order.courier_uuid {
+            if let Err(error) = notify_courier_about_cancellation(context, &order, reason).await {
+                tracing::error!(
+                    order_uuid = %order_uuid,
+                    courier_uuid = %courier_uuid,
+                    error.message = ?error.message,
+                    "Cannot notify courier about canceled order"
+                );
             }
-            _ => Err("This order already finished".into()),
         }
+        Ok("Order canceled".to_string())
     }
 
     pub async fn wait_for_free_courier(
@@ -1,4 +1,4 @@
-use delivery_order::utils::configs::{Application, Config, GrpcServer};
+use delivery_order::utils::configs::{Application, BucketExpiryJob, Config, GrpcServer};
 use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 
@@ -7,14 +7,17 @@ async fn main() -> Result<(), anyhow::Error> {
     let config = Config::init().await;
 
     let application = Application::build(&config).await?;
-    let grpc_server = GrpcServer::build().await?;
+    let grpc_server = GrpcServer::build(&config).await?;
+    let bucket_expiry_job = BucketExpiryJob::build(&config).await?;
 
     let application_task = tokio::spawn(application.run_untill_stopped());
     let grpc_server_task = tokio::spawn(grpc_server.run_untill_stopped(config.clone()));
+    let bucket_expiry_task = tokio::spawn(bucket_expiry_job.run_untill_stopped());
 
     tokio::select! {
         task = application_task => report_exit("Application", task),
         task = grpc_server_task =>  report_exit("gRPC Server", task),
+        task = bucket_expiry_task => report_exit("Bucket expiry job", task),
     };
     Ok(())
 }
@@ -1,26 +1,288 @@
-use crate::schema::diesel_schema::{bucket, order_item, orders, product};
-use chrono::NaiveDateTime;
+use crate::schema::diesel_schema::{
+    bucket, category, checkout, modifier_group, modifier_option, order_idempotency_key, order_item,
+    order_status_history, orders, product, restaurant, restaurant_holiday,
+    restaurant_opening_hours, sql_types,
+};
+use async_graphql::connection::{Connection, OpaqueCursor};
+use async_graphql::{
+    Enum, InputObject, InputValueError, InputValueResult, MaybeUndefined, Scalar, ScalarType,
+    SimpleObject, Value,
+};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::BigInt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
 use uuid::Uuid;
 
+// Amount of money in minor units of currency (cents).
+// Currency code is stored next to the amount
+#[derive(AsExpression, FromSqlRow, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[diesel(sql_type = BigInt)]
+pub struct Money(pub i64);
+
+// ISO 4217 currencies whose minor unit is not 1/100,
+// amounts in them cannot be represented by Money
+const NON_DECIMAL_CURRENCIES: &[&str] = &[
+    "BHD", "BIF", "CLF", "CLP", "DJF", "GNF", "IQD", "ISK", "JOD", "JPY", "KMF", "KRW", "KWD",
+    "LYD", "OMR", "PYG", "RWF", "TND", "UGX", "UYI", "UYW", "VND", "VUV", "XAF", "XOF", "XPF",
+];
+
+impl Money {
+    const MINOR_UNITS: i64 = 100;
+
+    pub fn times(self, amount: i16) -> Result<Money, MoneyOverflowError> {
+        self.0
+            .checked_mul(amount.into())
+            .map(Money)
+            .ok_or(MoneyOverflowError)
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyOverflowError> {
+        self.0
+            .checked_add(other.0)
+            .map(Money)
+            .ok_or(MoneyOverflowError)
+    }
+
+    pub fn total<I: IntoIterator<Item = Money>>(amounts: I) -> Result<Money, MoneyOverflowError> {
+        amounts
+            .into_iter()
+            .try_fold(Money::default(), Money::checked_add)
+    }
+
+    // Only currencies with two decimal places are supported
+    pub fn parse_currency(code: &str) -> Result<String, String> {
+        let code = code.to_uppercase();
+        if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(format!("\"{}\" is not ISO 4217 currency code", code));
+        }
+        if NON_DECIMAL_CURRENCIES.contains(&code.as_str()) {
+            return Err(format!(
+                "Currency {} is not supported, only currencies with 2 decimal places are",
+                code
+            ));
+        }
+        Ok(code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoneyOverflowError;
+
+impl fmt::Display for MoneyOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Amount of money is out of range")
+    }
+}
+
+impl std::error::Error for MoneyOverflowError {}
+
+// Formats amount in major units, e.g. "12.50"
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let amount = self.0.unsigned_abs();
+        let minor_units = Money::MINOR_UNITS as u64;
+        write!(
+            f,
+            "{}{}.{:02}",
+            sign,
+            amount / minor_units,
+            amount % minor_units
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseMoneyError;
+
+impl fmt::Display for ParseMoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid money amount, expected format is \"12.50\"")
+    }
+}
+
+impl std::error::Error for ParseMoneyError {}
+
+// Parses amount in major units without going through floats
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (major, minor) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if major.is_empty() || !is_digits(major) || !is_digits(minor) || minor.len() > 2 {
+            return Err(ParseMoneyError);
+        }
+        let major = major.parse::<i64>().map_err(|_| ParseMoneyError)?;
+        let minor = format!("{:0<2}", minor)
+            .parse::<i64>()
+            .map_err(|_| ParseMoneyError)?;
+        let amount = major
+            .checked_mul(Money::MINOR_UNITS)
+            .and_then(|amount| amount.checked_add(minor))
+            .ok_or(ParseMoneyError)?;
+        Ok(Money(if negative { -amount } else { amount }))
+    }
+}
+
+// Money is represented as decimal string in major units, e.g. "12.50".
+// Integers are accepted as input as well, floats are rejected to avoid rounding errors
+#[Scalar]
+impl ScalarType for Money {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => Ok(s.parse()?),
+            Value::Number(n) => n
+                .as_i64()
+                .and_then(|major| major.checked_mul(Money::MINOR_UNITS))
+                .map(Money)
+                .ok_or_else(|| InputValueError::custom(ParseMoneyError)),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToSql<BigInt, Pg> for Money {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Pg>>::to_sql(&self.0, &mut out.reborrow())
+    }
+}
+
+impl FromSql<BigInt, Pg> for Money {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        i64::from_sql(bytes).map(Money)
+    }
+}
+
+// Mirrors "order_status" Postgres enum
+#[derive(Enum, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = sql_types::OrderStatus)]
+pub enum OrderStatus {
+    WaitingForCourier,
+    Assigned,
+    PickedUp,
+    InProgress,
+    Finished,
+    Canceled,
+    // Courier was not found in time
+    Expired,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::WaitingForCourier => "WAITING_FOR_COURIER",
+            OrderStatus::Assigned => "ASSIGNED",
+            OrderStatus::PickedUp => "PICKED_UP",
+            OrderStatus::InProgress => "IN_PROGRESS",
+            OrderStatus::Finished => "FINISHED",
+            OrderStatus::Canceled => "CANCELED",
+            OrderStatus::Expired => "EXPIRED",
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<sql_types::OrderStatus, Pg> for OrderStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::OrderStatus, Pg> for OrderStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"WAITING_FOR_COURIER" => Ok(OrderStatus::WaitingForCourier),
+            b"ASSIGNED" => Ok(OrderStatus::Assigned),
+            b"PICKED_UP" => Ok(OrderStatus::PickedUp),
+            b"IN_PROGRESS" => Ok(OrderStatus::InProgress),
+            b"FINISHED" => Ok(OrderStatus::Finished),
+            b"CANCELED" => Ok(OrderStatus::Canceled),
+            b"EXPIRED" => Ok(OrderStatus::Expired),
+            _ => Err("Unrecognized order status".into()),
+        }
+    }
+}
+
 #[derive(Queryable)]
 pub struct Order {
     pub uuid: Uuid,
     pub user_uuid: Uuid,
-    pub courier_uuid: Uuid,
+    pub courier_uuid: Option<Uuid>,
     pub rating: Option<i16>,
-    pub status: String,
+    pub status: OrderStatus,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub address: String,
+    pub canceled_by: Option<Uuid>,
+    pub cancel_reason: Option<String>,
+    pub delivery_fee: Money,
+    pub currency: String,
+    pub checkout_uuid: Uuid,
+    pub restaurant_uuid: Option<Uuid>,
 }
 
+// Order without courier waits for courier with WaitingForCourier status
 #[derive(Insertable)]
 #[diesel(table_name = orders)]
 pub struct CreateOrder {
+    pub checkout_uuid: Uuid,
+    pub restaurant_uuid: Option<Uuid>,
     pub user_uuid: Uuid,
-    pub courier_uuid: Uuid,
+    pub courier_uuid: Option<Uuid>,
+    pub status: OrderStatus,
     pub address: String,
+    pub delivery_fee: Money,
+    pub currency: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = order_idempotency_key)]
+pub struct CreateIdempotencyKey {
+    pub user_uuid: Uuid,
+    pub idempotency_key: String,
+    pub checkout_uuid: Uuid,
+    pub expires_at: NaiveDateTime,
+}
+
+// Orders created from one bucket, one order per restaurant
+#[derive(Queryable, Clone)]
+pub struct Checkout {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub address: String,
+    pub currency: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = checkout)]
+pub struct CreateCheckout {
+    pub user_uuid: Uuid,
+    pub address: String,
+    pub currency: String,
 }
 
 #[derive(Queryable)]
@@ -32,14 +294,25 @@ pub struct OrderItems {
     pub amount: i16,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub product_name: String,
+    pub unit_price: Money,
+    pub option_uuids: Vec<Uuid>,
+    pub option_names: Vec<String>,
 }
 
+// Product name and price are copied at order creation,
+// so later product updates don't change existing orders
 #[derive(Queryable, Insertable, Clone)]
 #[diesel(table_name = order_item)]
 pub struct OrderItem {
     pub order_uuid: Uuid,
     pub product_uuid: Uuid,
     pub amount: i16,
+    pub product_name: String,
+    // includes price deltas of selected options
+    pub unit_price: Money,
+    pub option_uuids: Vec<Uuid>,
+    pub option_names: Vec<String>,
 }
 
 #[derive(Queryable)]
@@ -50,64 +323,364 @@ pub struct Bucket {
     pub amount: i16,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub option_uuids: Vec<Uuid>,
 }
 
 #[derive(Queryable)]
 pub struct Product {
     pub uuid: Uuid,
     pub name: String,
-    pub price: f64,
-    pub product_type: String,
-    pub restaurant: String,
+    pub price: Money,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub currency: String,
+    pub restaurant_uuid: Uuid,
+    pub category_uuid: Uuid,
+    pub stock: Option<i32>,
+    pub is_available: bool,
+    pub archived_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = product)]
 pub struct CreateProduct {
     pub name: String,
-    pub price: f64,
-    pub product_type: String,
-    pub restaurant: String,
+    pub price: Money,
+    pub category_uuid: Uuid,
+    pub restaurant_uuid: Uuid,
+    pub currency: String,
 }
 
 #[derive(AsChangeset)]
 #[diesel(table_name = product)]
 pub struct UpdateProduct {
     pub name: Option<String>,
-    pub price: Option<f64>,
-    pub product_type: Option<String>,
-    pub restaurant: Option<String>,
+    pub price: Option<Money>,
+    pub category_uuid: Option<Uuid>,
+    pub restaurant_uuid: Option<Uuid>,
 }
 
-#[derive(Queryable, Insertable, Clone)]
+#[derive(Queryable, QueryableByName, Insertable, Clone)]
 #[diesel(table_name = product)]
 pub struct ProductInfo {
     pub uuid: Uuid,
     pub name: String,
-    pub price: f64,
-    pub product_type: String,
-    pub restaurant: String,
+    pub price: Money,
+    pub category_uuid: Uuid,
+    pub restaurant_uuid: Uuid,
+    pub currency: String,
+    pub created_at: NaiveDateTime,
+    // not tracked when None
+    pub stock: Option<i32>,
+    pub is_available: bool,
+    pub archived_at: Option<NaiveDateTime>,
+}
+
+// Customer selects from min_select to max_select options of a group
+#[derive(Queryable, Clone)]
+pub struct ModifierGroup {
+    pub uuid: Uuid,
+    pub product_uuid: Uuid,
+    pub name: String,
+    pub min_select: i32,
+    pub max_select: i32,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = modifier_group)]
+pub struct CreateModifierGroup {
+    pub product_uuid: Uuid,
+    pub name: String,
+    pub min_select: i32,
+    pub max_select: i32,
+    pub position: i32,
+}
+
+#[derive(Queryable, Clone)]
+pub struct ModifierOption {
+    pub uuid: Uuid,
+    pub group_uuid: Uuid,
+    pub name: String,
+    pub price_delta: Money,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = modifier_option)]
+pub struct CreateModifierOption {
+    pub group_uuid: Uuid,
+    pub name: String,
+    pub price_delta: Money,
+    pub position: i32,
+}
+
+#[derive(Queryable, Clone)]
+pub struct Category {
+    pub uuid: Uuid,
+    pub parent_uuid: Option<Uuid>,
+    pub name: String,
+    pub display_name: String,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// Category with its nested children, ordered by position
+#[derive(Clone)]
+pub struct CategoryNode {
+    pub category: Category,
+    pub children: Vec<CategoryNode>,
+}
+
+// Category in depth-first order of the tree, top level categories have depth 0.
+// Clients assemble tree of any depth from "parent_uuid"
+#[derive(Clone)]
+pub struct FlatCategory {
+    pub category: Category,
+    pub depth: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = category)]
+pub struct CreateCategory {
+    pub parent_uuid: Option<Uuid>,
+    pub name: String,
+    pub display_name: String,
+    pub position: i32,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = category)]
+pub struct UpdateCategory {
+    pub parent_uuid: Option<Option<Uuid>>,
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub position: Option<i32>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct Restaurant {
+    pub uuid: Uuid,
+    pub name: String,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    // IANA time zone, e.g. "Europe/Berlin"
+    pub time_zone: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub account_uuid: Option<Uuid>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = restaurant)]
+pub struct CreateRestaurant {
+    pub name: String,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub time_zone: String,
+    pub account_uuid: Option<Uuid>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = restaurant)]
+pub struct UpdateRestaurant {
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub time_zone: Option<String>,
+    pub account_uuid: Option<Option<Uuid>>,
+    pub updated_at: NaiveDateTime,
+}
+
+// Weekday is ISO day of week: 1 is Monday, 7 is Sunday
+#[derive(Queryable, Clone)]
+pub struct OpeningHours {
+    pub weekday: i16,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = restaurant_opening_hours)]
+pub struct CreateOpeningHours {
+    pub restaurant_uuid: Uuid,
+    pub weekday: i16,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+// Restaurant is closed the whole day when hours are not set
+#[derive(Queryable, Insertable, Clone)]
+#[diesel(table_name = restaurant_holiday)]
+pub struct Holiday {
+    pub restaurant_uuid: Uuid,
+    pub date: NaiveDate,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    pub note: Option<String>,
+}
+
+#[derive(InputObject, Clone)]
+pub struct OpeningHoursInput {
+    pub weekday: i16,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+#[derive(InputObject, Clone)]
+pub struct HolidayInput {
+    pub date: NaiveDate,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    pub note: Option<String>,
+}
+
+// Opening hours and holidays are replaced as a whole when set
+#[derive(InputObject)]
+pub struct RestaurantInput {
+    pub name: String,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    // IANA time zone, e.g. "Europe/Berlin"
+    #[graphql(default_with = "String::from(\"UTC\")")]
+    pub time_zone: String,
+    // user account of restaurant staff
+    pub account_uuid: Option<Uuid>,
+    #[graphql(default)]
+    pub opening_hours: Vec<OpeningHoursInput>,
+    #[graphql(default)]
+    pub holidays: Vec<HolidayInput>,
+}
+
+#[derive(InputObject)]
+pub struct UpdateRestaurantInput {
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub time_zone: Option<String>,
+    pub account_uuid: MaybeUndefined<Uuid>,
+    pub opening_hours: Option<Vec<OpeningHoursInput>>,
+    pub holidays: Option<Vec<HolidayInput>>,
 }
 
+// Selected options are sorted by uuid
 #[derive(Queryable, Insertable, Clone)]
 #[diesel(table_name = bucket)]
 pub struct BucketItem {
     pub user_uuid: Uuid,
     pub product_uuid: Uuid,
     pub amount: i16,
+    pub option_uuids: Vec<Uuid>,
+}
+
+// Bucket item priced with current product and option prices
+#[derive(Clone)]
+pub struct BucketLine {
+    pub product_uuid: Uuid,
+    pub product_name: String,
+    pub amount: i16,
+    pub option_uuids: Vec<Uuid>,
+    pub option_names: Vec<String>,
+    pub unit_price: Money,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketWarningCode {
+    EmptyBucket,
+    // product was deleted after it was added to bucket
+    ProductNotFound,
+    ProductArchived,
+    ProductUnavailable,
+    InvalidOptions,
+    CurrencyMismatch,
+    RestaurantClosed,
+    BelowMinimumOrder,
+}
+
+// Reason why bucket cannot be ordered as is
+#[derive(Clone)]
+pub struct BucketWarning {
+    pub code: BucketWarningCode,
+    pub message: String,
+    pub product_uuid: Option<Uuid>,
+    pub restaurant_uuid: Option<Uuid>,
+}
+
+#[derive(Clone)]
+pub struct BucketSummary {
+    pub user_uuid: Uuid,
+    pub lines: Vec<BucketLine>,
+    pub subtotal: Money,
+    pub delivery_fee: Money,
+    pub currency: String,
+    pub warnings: Vec<BucketWarning>,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReorderSkipReason {
+    ProductArchived,
+    OutOfStock,
+    OptionsUnavailable,
+}
+
+#[derive(Clone)]
+pub struct SkippedOrderItem {
+    pub item: OrderItem,
+    pub reason: ReorderSkipReason,
+}
+
+// Result of copying order items back into bucket
+#[derive(Clone)]
+pub struct ReorderReport {
+    pub added: Vec<BucketItem>,
+    pub skipped: Vec<SkippedOrderItem>,
 }
 
 #[derive(Queryable, Clone)]
 pub struct OrderInfo {
     pub uuid: Uuid,
     pub user_uuid: Uuid,
-    pub courier_uuid: Uuid,
+    // None while order is waiting for courier
+    pub courier_uuid: Option<Uuid>,
     pub rating: Option<i16>,
-    pub status: String,
+    pub status: OrderStatus,
+    pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub address: String,
+    pub canceled_by: Option<Uuid>,
+    pub cancel_reason: Option<String>,
+    pub delivery_fee: Money,
+    pub currency: String,
+    pub checkout_uuid: Uuid,
+    pub restaurant_uuid: Option<Uuid>,
+}
+
+#[derive(Queryable, Clone)]
+pub struct OrderStatusEvent {
+    pub changed_by: Option<Uuid>,
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = order_status_history)]
+pub struct CreateOrderStatusEvent {
+    pub order_uuid: Uuid,
+    pub changed_by: Option<Uuid>,
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub note: Option<String>,
 }
 
 #[derive(Clone)]
@@ -115,3 +688,196 @@ pub struct OrderQueueInfo {
     pub status: String,
     pub avg_waiting_time: i32,
 }
+
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn reverse(self) -> SortDirection {
+        match self {
+            SortDirection::Asc => SortDirection::Desc,
+            SortDirection::Desc => SortDirection::Asc,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct ProductFilter {
+    pub name: Option<String>,
+    // expanded with nested categories before querying
+    pub category_uuids: Option<Vec<Uuid>>,
+    pub restaurant_uuid: Option<Uuid>,
+    pub price_order: Option<SortDirection>,
+    pub only_available: bool,
+}
+
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSortField {
+    #[default]
+    CreatedAt,
+    Rating,
+}
+
+// Date ranges are inclusive, restaurant_uuid matches orders containing
+// at least one product of that restaurant
+#[derive(InputObject, Default, Clone)]
+pub struct OrderFilter {
+    pub order_uuid: Option<Uuid>,
+    pub courier_uuid: Option<Uuid>,
+    pub user_uuid: Option<Uuid>,
+    pub address: Option<String>,
+    pub statuses: Option<Vec<OrderStatus>>,
+    pub created_from: Option<NaiveDateTime>,
+    pub created_to: Option<NaiveDateTime>,
+    pub updated_from: Option<NaiveDateTime>,
+    pub updated_to: Option<NaiveDateTime>,
+    pub rating_from: Option<i16>,
+    pub rating_to: Option<i16>,
+    pub restaurant_uuid: Option<Uuid>,
+    #[graphql(default)]
+    pub sort_by: OrderSortField,
+    #[graphql(default)]
+    pub sort_direction: SortDirection,
+}
+
+// Keyset pagination parameters.
+// Backward pages are fetched in reversed order and reversed back afterwards
+pub struct Page<C> {
+    pub after: Option<C>,
+    pub before: Option<C>,
+    pub size: usize,
+    pub backward: bool,
+}
+
+impl<C> Page<C> {
+    // One extra row shows whether there are more rows after the page
+    pub fn fetch_limit(&self) -> i64 {
+        self.size as i64 + 1
+    }
+}
+
+// Products are ordered by (price, created_at, uuid) when sorted by price
+// and by (created_at, uuid) otherwise
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProductCursor {
+    pub price: i64,
+    pub created_at: NaiveDateTime,
+    pub uuid: Uuid,
+}
+
+impl From<&ProductInfo> for ProductCursor {
+    fn from(product: &ProductInfo) -> Self {
+        ProductCursor {
+            price: product.price.0,
+            created_at: product.created_at,
+            uuid: product.uuid,
+        }
+    }
+}
+
+// Orders are ordered by (rating, created_at, uuid) when sorted by rating
+// and by (created_at, uuid) otherwise. Unrated orders go as rating 0
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OrderCursor {
+    pub rating: i16,
+    pub created_at: NaiveDateTime,
+    pub uuid: Uuid,
+}
+
+impl From<&OrderInfo> for OrderCursor {
+    fn from(order: &OrderInfo) -> Self {
+        OrderCursor {
+            rating: order.rating.unwrap_or(0),
+            created_at: order.created_at,
+            uuid: order.uuid,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TotalCount {
+    pub total_count: i64,
+}
+
+pub type ProductConnection = Connection<OpaqueCursor<ProductCursor>, ProductInfo, TotalCount>;
+pub type OrderConnection = Connection<OpaqueCursor<OrderCursor>, OrderInfo, TotalCount>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn money_is_parsed_from_major_units() {
+        assert_eq!("12.50".parse(), Ok(Money(1250)));
+        assert_eq!("12.5".parse(), Ok(Money(1250)));
+        assert_eq!("12".parse(), Ok(Money(1200)));
+        assert_eq!("0.05".parse(), Ok(Money(5)));
+        assert_eq!("-3.10".parse(), Ok(Money(-310)));
+    }
+
+    #[test]
+    fn invalid_money_is_rejected() {
+        for input in [
+            "",
+            ".50",
+            "1.234",
+            "1,50",
+            "abc",
+            "1.5e2",
+            "--1",
+            "9223372036854775807",
+        ] {
+            assert_eq!(input.parse::<Money>(), Err(ParseMoneyError), "{}", input);
+        }
+    }
+
+    #[test]
+    fn money_is_formatted_in_major_units() {
+        assert_eq!(Money(1250).to_string(), "12.50");
+        assert_eq!(Money(5).to_string(), "0.05");
+        assert_eq!(Money(-310).to_string(), "-3.10");
+        assert_eq!(Money(i64::MIN).to_string(), "-92233720368547758.08");
+    }
+
+    #[test]
+    fn money_scalar_accepts_strings_and_integers() {
+        assert_eq!(
+            Money::parse(Value::String("1.99".to_string())).ok(),
+            Some(Money(199))
+        );
+        assert_eq!(Money::parse(Value::from(3)).ok(), Some(Money(300)));
+        assert!(Money::parse(Value::from(1.5)).is_err());
+        assert!(Money::parse(Value::from(i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn money_arithmetic_is_checked() {
+        assert_eq!(Money(250).times(3), Ok(Money(750)));
+        assert_eq!(Money(i64::MAX).times(2), Err(MoneyOverflowError));
+        assert_eq!(Money(1).checked_add(Money(2)), Ok(Money(3)));
+        assert_eq!(
+            Money(i64::MAX).checked_add(Money(1)),
+            Err(MoneyOverflowError)
+        );
+        assert_eq!(Money::total([Money(1), Money(2), Money(3)]), Ok(Money(6)));
+        assert_eq!(Money::total([]), Ok(Money(0)));
+        assert_eq!(
+            Money::total([Money(i64::MAX), Money(1)]),
+            Err(MoneyOverflowError)
+        );
+    }
+
+    #[test]
+    fn only_two_decimal_currencies_are_supported() {
+        assert_eq!(Money::parse_currency("USD"), Ok("USD".to_string()));
+        assert_eq!(Money::parse_currency("eur"), Ok("EUR".to_string()));
+        assert!(Money::parse_currency("JPY").is_err());
+        assert!(Money::parse_currency("KWD").is_err());
+        assert!(Money::parse_currency("US").is_err());
+        assert!(Money::parse_currency("U5D").is_err());
+    }
+}
@@ -1,61 +1,266 @@
 use crate::models::orders_model::*;
-use crate::resources::postgresql::DbConn;
+use crate::schema::diesel_schema::{checkout, order_item, orders, product};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::result::Error;
-use diesel_async::RunQueryDsl;
+use diesel::sql_types::{BigInt, Bool, Nullable, SmallInt, Text};
+use diesel::upsert::excluded;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use uuid::Uuid;
 
-pub async fn select_products_by_filter(
-    db_conn: &mut DbConn<'_>,
-    pr_name: Option<String>,
-    price_from_cheap: Option<bool>,
-    price_from_expensive: Option<bool>,
-    pr_type: Option<String>,
-    pr_restaurant: Option<String>,
-) -> Result<Vec<ProductInfo>, Error> {
+// Columns selected into OrderInfo, kept in one place for every orders query
+type OrderInfoColumns = (
+    orders::uuid,
+    orders::user_uuid,
+    orders::courier_uuid,
+    orders::rating,
+    orders::status,
+    orders::created_at,
+    orders::updated_at,
+    orders::address,
+    orders::canceled_by,
+    orders::cancel_reason,
+    orders::delivery_fee,
+    orders::currency,
+    orders::checkout_uuid,
+    orders::restaurant_uuid,
+);
+
+const ORDER_INFO_COLUMNS: OrderInfoColumns = (
+    orders::uuid,
+    orders::user_uuid,
+    orders::courier_uuid,
+    orders::rating,
+    orders::status,
+    orders::created_at,
+    orders::updated_at,
+    orders::address,
+    orders::canceled_by,
+    orders::cancel_reason,
+    orders::delivery_fee,
+    orders::currency,
+    orders::checkout_uuid,
+    orders::restaurant_uuid,
+);
+
+// Columns selected into ProductInfo
+type ProductInfoColumns = (
+    product::uuid,
+    product::name,
+    product::price,
+    product::category_uuid,
+    product::restaurant_uuid,
+    product::currency,
+    product::created_at,
+    product::stock,
+    product::is_available,
+    product::archived_at,
+);
+
+const PRODUCT_INFO_COLUMNS: ProductInfoColumns = (
+    product::uuid,
+    product::name,
+    product::price,
+    product::category_uuid,
+    product::restaurant_uuid,
+    product::currency,
+    product::created_at,
+    product::stock,
+    product::is_available,
+    product::archived_at,
+);
+
+type ProductCondition = Box<dyn BoxableExpression<product::table, Pg, SqlType = Bool>>;
+type OrderCondition = Box<dyn BoxableExpression<orders::table, Pg, SqlType = Bool>>;
+
+// Escapes LIKE wildcards in user input
+fn contains_pattern(value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", value)
+}
+
+fn filter_products<'a>(filter: &ProductFilter) -> product::BoxedQuery<'a, Pg> {
     use crate::schema::diesel_schema::product::dsl::*;
-    let mut query = product.into_boxed();
+    let mut query = product.filter(archived_at.is_null()).into_boxed();
+
+    if let Some(pr_name) = &filter.name {
+        query = query.filter(name.ilike(contains_pattern(pr_name)));
+    }
 
-    if let Some(pr_name) = pr_name {
-        query = query.order(name.like(pr_name));
+    if let Some(category_uuids) = &filter.category_uuids {
+        query = query.filter(category_uuid.eq_any(category_uuids.clone()));
     }
 
-    if let Some(true) = price_from_cheap {
-        query = query.order(price.asc());
+    if let Some(pr_restaurant) = filter.restaurant_uuid {
+        query = query.filter(restaurant_uuid.eq(pr_restaurant));
     }
 
-    if let Some(true) = price_from_expensive {
-        query = query.order(price.desc());
+    if filter.only_available {
+        query = query
+            .filter(is_available.eq(true))
+            .filter(stock.is_null().or(stock.gt(0)));
+    }
+    query
+}
+
+// Products placed after (or before) cursor in pagination order
+fn products_beyond_cursor(
+    cursor: &ProductCursor,
+    price_order: Option<SortDirection>,
+    after: bool,
+) -> ProductCondition {
+    use crate::schema::diesel_schema::product::dsl::*;
+    let time_and_uuid: ProductCondition = if after {
+        Box::new(
+            created_at
+                .gt(cursor.created_at)
+                .or(created_at.eq(cursor.created_at).and(uuid.gt(cursor.uuid))),
+        )
+    } else {
+        Box::new(
+            created_at
+                .lt(cursor.created_at)
+                .or(created_at.eq(cursor.created_at).and(uuid.lt(cursor.uuid))),
+        )
+    };
+
+    match price_order {
+        None => time_and_uuid,
+        Some(direction) => {
+            let cursor_price = Money(cursor.price);
+            let price_beyond: ProductCondition = if (direction == SortDirection::Asc) == after {
+                Box::new(price.gt(cursor_price))
+            } else {
+                Box::new(price.lt(cursor_price))
+            };
+            Box::new(price_beyond.or(price.eq(cursor_price).and(time_and_uuid)))
+        }
+    }
+}
+
+pub async fn count_products(
+    db_conn: &mut AsyncPgConnection,
+    filter: &ProductFilter,
+) -> Result<i64, Error> {
+    filter_products(filter).count().get_result(db_conn).await
+}
+
+pub async fn select_products_page(
+    db_conn: &mut AsyncPgConnection,
+    filter: &ProductFilter,
+    page: &Page<ProductCursor>,
+) -> Result<Vec<ProductInfo>, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    let mut query = filter_products(filter);
+
+    if let Some(cursor) = &page.after {
+        query = query.filter(products_beyond_cursor(cursor, filter.price_order, true));
     }
 
-    if let Some(pr_type) = pr_type {
-        query = query.filter(product_type.eq(pr_type));
+    if let Some(cursor) = &page.before {
+        query = query.filter(products_beyond_cursor(cursor, filter.price_order, false));
     }
 
-    if let Some(pr_restaurant) = pr_restaurant {
-        query = query.filter(restaurant.eq(pr_restaurant));
+    if let Some(direction) = filter.price_order {
+        let direction = if page.backward {
+            direction.reverse()
+        } else {
+            direction
+        };
+        query = match direction {
+            SortDirection::Asc => query.then_order_by(price.asc()),
+            SortDirection::Desc => query.then_order_by(price.desc()),
+        };
     }
 
+    query = if page.backward {
+        query.then_order_by((created_at.desc(), uuid.desc()))
+    } else {
+        query.then_order_by((created_at.asc(), uuid.asc()))
+    };
+
     query
-        .select((uuid, name, price, product_type, restaurant))
+        .select(PRODUCT_INFO_COLUMNS)
+        .limit(page.fetch_limit())
         .get_results(db_conn)
         .await
 }
 
 pub async fn select_product(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     product_uuid: Uuid,
 ) -> Result<ProductInfo, Error> {
     use crate::schema::diesel_schema::product::dsl::*;
     product
         .filter(uuid.eq(product_uuid))
-        .select((uuid, name, price, product_type, restaurant))
+        .select(PRODUCT_INFO_COLUMNS)
         .get_result(db_conn)
         .await
 }
 
+// Full-text match on name, category and restaurant plus trigram word similarity
+// for typos. search_vector is a generated column, so it isn't in diesel schema
+const SEARCH_PRODUCTS_QUERY: &str = "
+    SELECT uuid, name, price, category_uuid, restaurant_uuid, currency, created_at, stock,
+        is_available, archived_at
+    FROM (
+        SELECT product.*,
+            ts_rank(
+                product.search_vector
+                    || setweight(to_tsvector('simple', category.display_name), 'B')
+                    || setweight(to_tsvector('simple', restaurant.name), 'C'),
+                websearch_to_tsquery('simple', $1)
+            ) AS text_rank,
+            GREATEST(
+                word_similarity($1, product.name),
+                word_similarity($1, category.display_name) * 0.8,
+                word_similarity($1, restaurant.name) * 0.6
+            ) AS similarity_rank
+        FROM product
+        INNER JOIN category ON category.uuid = product.category_uuid
+        INNER JOIN restaurant ON restaurant.uuid = product.restaurant_uuid
+        WHERE product.archived_at IS NULL AND (
+            product.search_vector @@ websearch_to_tsquery('simple', $1)
+            OR to_tsvector('simple', category.display_name) @@ websearch_to_tsquery('simple', $1)
+            OR to_tsvector('simple', restaurant.name) @@ websearch_to_tsquery('simple', $1)
+            OR $1 <% product.name
+            OR $1 <% category.display_name
+            OR $1 <% restaurant.name
+        )
+    ) AS found
+    ORDER BY text_rank + similarity_rank DESC, created_at, uuid
+    LIMIT $2";
+
+pub async fn search_products(
+    db_conn: &mut AsyncPgConnection,
+    search_query: &str,
+    limit: i64,
+) -> Result<Vec<ProductInfo>, Error> {
+    diesel::sql_query(SEARCH_PRODUCTS_QUERY)
+        .bind::<Text, _>(search_query)
+        .bind::<BigInt, _>(limit)
+        .load(db_conn)
+        .await
+}
+
+pub async fn select_products_by_uuids(
+    db_conn: &mut AsyncPgConnection,
+    product_uuids: Vec<Uuid>,
+) -> Result<Vec<ProductInfo>, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    product
+        .filter(uuid.eq_any(product_uuids))
+        .select(PRODUCT_INFO_COLUMNS)
+        .get_results(db_conn)
+        .await
+}
+
 pub async fn create_product(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     new_product: CreateProduct,
 ) -> Result<usize, Error> {
     use crate::schema::diesel_schema::product::dsl::*;
@@ -66,55 +271,499 @@ pub async fn create_product(
 }
 
 pub async fn update_product(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     new_product: UpdateProduct,
     product_uuid: Uuid,
 ) -> Result<ProductInfo, Error> {
     use crate::schema::diesel_schema::product::dsl::*;
     diesel::update(product.find(product_uuid))
         .set(new_product)
-        .returning((uuid, name, price, product_type, restaurant))
+        .returning(PRODUCT_INFO_COLUMNS)
+        .get_result(db_conn)
+        .await
+}
+
+// Soft deletes product, returns None when it is already archived
+pub async fn archive_product(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+) -> Result<Option<ProductInfo>, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    diesel::update(product.find(product_uuid).filter(archived_at.is_null()))
+        .set((
+            archived_at.eq(diesel::dsl::now.nullable()),
+            updated_at.eq(diesel::dsl::now),
+        ))
+        .returning(PRODUCT_INFO_COLUMNS)
+        .get_result(db_conn)
+        .await
+        .optional()
+}
+
+pub async fn restore_product(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+) -> Result<Option<ProductInfo>, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    diesel::update(product.find(product_uuid).filter(archived_at.is_not_null()))
+        .set((
+            archived_at.eq(None::<NaiveDateTime>),
+            updated_at.eq(diesel::dsl::now),
+        ))
+        .returning(PRODUCT_INFO_COLUMNS)
+        .get_result(db_conn)
+        .await
+        .optional()
+}
+
+// Decrements tracked stock, returns 0 when product is unavailable
+// or there is not enough stock
+pub async fn reserve_product_stock(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+    amount: i32,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    diesel::update(
+        product
+            .find(product_uuid)
+            .filter(archived_at.is_null())
+            .filter(is_available.eq(true))
+            .filter(stock.is_null().or(stock.ge(amount))),
+    )
+    .set(stock.eq(stock - amount))
+    .execute(db_conn)
+    .await
+}
+
+pub async fn release_product_stock(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+    amount: i32,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    diesel::update(product.find(product_uuid).filter(stock.is_not_null()))
+        .set(stock.eq(stock + amount))
+        .execute(db_conn)
+        .await
+}
+
+// Sets stock only if it was not changed since it has been read
+pub async fn update_product_stock(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+    current_stock: Option<i32>,
+    new_stock: Option<i32>,
+    available: bool,
+) -> Result<Option<ProductInfo>, Error> {
+    use crate::schema::diesel_schema::product::dsl::*;
+    diesel::update(
+        product
+            .find(product_uuid)
+            .filter(stock.is_not_distinct_from(current_stock)),
+    )
+    .set((
+        stock.eq(new_stock),
+        is_available.eq(available),
+        updated_at.eq(diesel::dsl::now),
+    ))
+    .returning(PRODUCT_INFO_COLUMNS)
+    .get_result(db_conn)
+    .await
+    .optional()
+}
+
+pub async fn select_modifier_groups(
+    db_conn: &mut AsyncPgConnection,
+    uuid_product: Uuid,
+) -> Result<Vec<ModifierGroup>, Error> {
+    use crate::schema::diesel_schema::modifier_group::dsl::*;
+    modifier_group
+        .filter(product_uuid.eq(uuid_product))
+        .order((position.asc(), created_at.asc()))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn select_modifier_options(
+    db_conn: &mut AsyncPgConnection,
+    uuid_group: Uuid,
+) -> Result<Vec<ModifierOption>, Error> {
+    use crate::schema::diesel_schema::modifier_option::dsl::*;
+    modifier_option
+        .filter(group_uuid.eq(uuid_group))
+        .order((position.asc(), created_at.asc()))
+        .get_results(db_conn)
+        .await
+}
+
+// Options of all product groups in display order
+pub async fn select_product_modifier_options(
+    db_conn: &mut AsyncPgConnection,
+    uuid_product: Uuid,
+) -> Result<Vec<ModifierOption>, Error> {
+    use crate::schema::diesel_schema::{modifier_group, modifier_option};
+    modifier_option::table
+        .inner_join(modifier_group::table)
+        .filter(modifier_group::product_uuid.eq(uuid_product))
+        .order((
+            modifier_group::position.asc(),
+            modifier_group::created_at.asc(),
+            modifier_option::position.asc(),
+            modifier_option::created_at.asc(),
+        ))
+        .select(modifier_option::all_columns)
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn select_modifier_options_by_uuids(
+    db_conn: &mut AsyncPgConnection,
+    option_uuids: Vec<Uuid>,
+) -> Result<Vec<ModifierOption>, Error> {
+    use crate::schema::diesel_schema::modifier_option::dsl::*;
+    modifier_option
+        .filter(uuid.eq_any(option_uuids))
+        .order((position.asc(), created_at.asc()))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn create_modifier_group(
+    db_conn: &mut AsyncPgConnection,
+    new_group: CreateModifierGroup,
+) -> Result<ModifierGroup, Error> {
+    use crate::schema::diesel_schema::modifier_group::dsl::*;
+    diesel::insert_into(modifier_group)
+        .values(new_group)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn create_modifier_option(
+    db_conn: &mut AsyncPgConnection,
+    new_option: CreateModifierOption,
+) -> Result<ModifierOption, Error> {
+    use crate::schema::diesel_schema::modifier_option::dsl::*;
+    diesel::insert_into(modifier_option)
+        .values(new_option)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn delete_modifier_group(
+    db_conn: &mut AsyncPgConnection,
+    group_uuid: Uuid,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::modifier_group::dsl::*;
+    diesel::delete(modifier_group.find(group_uuid))
+        .execute(db_conn)
+        .await
+}
+
+pub async fn delete_modifier_option(
+    db_conn: &mut AsyncPgConnection,
+    option_uuid: Uuid,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::modifier_option::dsl::*;
+    diesel::delete(modifier_option.find(option_uuid))
+        .execute(db_conn)
+        .await
+}
+
+pub async fn select_category(
+    db_conn: &mut AsyncPgConnection,
+    category_uuid: Uuid,
+) -> Result<Category, Error> {
+    use crate::schema::diesel_schema::category::dsl::*;
+    category.find(category_uuid).first(db_conn).await
+}
+
+pub async fn select_categories(db_conn: &mut AsyncPgConnection) -> Result<Vec<Category>, Error> {
+    use crate::schema::diesel_schema::category::dsl::*;
+    category
+        .order((position.asc(), display_name.asc()))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn create_category(
+    db_conn: &mut AsyncPgConnection,
+    new_category: CreateCategory,
+) -> Result<Category, Error> {
+    use crate::schema::diesel_schema::category::dsl::*;
+    diesel::insert_into(category)
+        .values(new_category)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn update_category(
+    db_conn: &mut AsyncPgConnection,
+    category_uuid: Uuid,
+    changes: UpdateCategory,
+) -> Result<Category, Error> {
+    use crate::schema::diesel_schema::category::dsl::*;
+    diesel::update(category.find(category_uuid))
+        .set(changes)
         .get_result(db_conn)
         .await
 }
 
-pub async fn create_bucket_item(
-    db_conn: &mut DbConn<'_>,
+pub async fn select_restaurant(
+    db_conn: &mut AsyncPgConnection,
+    restaurant_uuid: Uuid,
+) -> Result<Restaurant, Error> {
+    use crate::schema::diesel_schema::restaurant::dsl::*;
+    restaurant.find(restaurant_uuid).first(db_conn).await
+}
+
+pub async fn select_restaurants(db_conn: &mut AsyncPgConnection) -> Result<Vec<Restaurant>, Error> {
+    use crate::schema::diesel_schema::restaurant::dsl::*;
+    restaurant.order(name.asc()).get_results(db_conn).await
+}
+
+// Restaurants of products in user bucket
+pub async fn select_bucket_restaurants(
+    db_conn: &mut AsyncPgConnection,
+    uuid_user: Uuid,
+) -> Result<Vec<Restaurant>, Error> {
+    use crate::schema::diesel_schema::{bucket, restaurant};
+    restaurant::table
+        .filter(
+            restaurant::uuid.eq_any(
+                bucket::table
+                    .inner_join(product::table)
+                    .filter(bucket::user_uuid.eq(uuid_user))
+                    .select(product::restaurant_uuid),
+            ),
+        )
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn create_restaurant(
+    db_conn: &mut AsyncPgConnection,
+    new_restaurant: CreateRestaurant,
+) -> Result<Restaurant, Error> {
+    use crate::schema::diesel_schema::restaurant::dsl::*;
+    diesel::insert_into(restaurant)
+        .values(new_restaurant)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn update_restaurant(
+    db_conn: &mut AsyncPgConnection,
+    restaurant_uuid: Uuid,
+    changes: UpdateRestaurant,
+) -> Result<Restaurant, Error> {
+    use crate::schema::diesel_schema::restaurant::dsl::*;
+    diesel::update(restaurant.find(restaurant_uuid))
+        .set(changes)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn select_opening_hours(
+    db_conn: &mut AsyncPgConnection,
+    uuid_restaurant: Uuid,
+) -> Result<Vec<OpeningHours>, Error> {
+    use crate::schema::diesel_schema::restaurant_opening_hours::dsl::*;
+    restaurant_opening_hours
+        .filter(restaurant_uuid.eq(uuid_restaurant))
+        .order((weekday.asc(), opens_at.asc()))
+        .select((weekday, opens_at, closes_at))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn select_restaurants_opening_hours(
+    db_conn: &mut AsyncPgConnection,
+    uuids_restaurant: Vec<Uuid>,
+) -> Result<Vec<(Uuid, OpeningHours)>, Error> {
+    use crate::schema::diesel_schema::restaurant_opening_hours::dsl::*;
+    restaurant_opening_hours
+        .filter(restaurant_uuid.eq_any(uuids_restaurant))
+        .order((restaurant_uuid.asc(), weekday.asc(), opens_at.asc()))
+        .select((restaurant_uuid, (weekday, opens_at, closes_at)))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn replace_opening_hours(
+    db_conn: &mut AsyncPgConnection,
+    uuid_restaurant: Uuid,
+    hours: Vec<CreateOpeningHours>,
+) -> Result<(), Error> {
+    use crate::schema::diesel_schema::restaurant_opening_hours::dsl::*;
+    diesel::delete(restaurant_opening_hours.filter(restaurant_uuid.eq(uuid_restaurant)))
+        .execute(db_conn)
+        .await?;
+    diesel::insert_into(restaurant_opening_hours)
+        .values(hours)
+        .execute(db_conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn select_holidays(
+    db_conn: &mut AsyncPgConnection,
+    uuid_restaurant: Uuid,
+) -> Result<Vec<Holiday>, Error> {
+    use crate::schema::diesel_schema::restaurant_holiday::dsl::*;
+    restaurant_holiday
+        .filter(restaurant_uuid.eq(uuid_restaurant))
+        .order(date.asc())
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn select_holiday(
+    db_conn: &mut AsyncPgConnection,
+    uuid_restaurant: Uuid,
+    holiday_date: NaiveDate,
+) -> Result<Option<Holiday>, Error> {
+    use crate::schema::diesel_schema::restaurant_holiday::dsl::*;
+    restaurant_holiday
+        .find((uuid_restaurant, holiday_date))
+        .first(db_conn)
+        .await
+        .optional()
+}
+
+pub async fn select_restaurants_holidays_between(
+    db_conn: &mut AsyncPgConnection,
+    uuids_restaurant: Vec<Uuid>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Holiday>, Error> {
+    use crate::schema::diesel_schema::restaurant_holiday::dsl::*;
+    restaurant_holiday
+        .filter(restaurant_uuid.eq_any(uuids_restaurant))
+        .filter(date.between(from, to))
+        .get_results(db_conn)
+        .await
+}
+
+pub async fn replace_holidays(
+    db_conn: &mut AsyncPgConnection,
+    uuid_restaurant: Uuid,
+    holidays: Vec<Holiday>,
+) -> Result<(), Error> {
+    use crate::schema::diesel_schema::restaurant_holiday::dsl::*;
+    diesel::delete(restaurant_holiday.filter(restaurant_uuid.eq(uuid_restaurant)))
+        .execute(db_conn)
+        .await?;
+    diesel::insert_into(restaurant_holiday)
+        .values(holidays)
+        .execute(db_conn)
+        .await?;
+    Ok(())
+}
+
+// Adds amount to existing line with the same product and options
+pub async fn upsert_bucket_item(
+    db_conn: &mut AsyncPgConnection,
     bucket_item: BucketItem,
 ) -> Result<BucketItem, Error> {
     use crate::schema::diesel_schema::bucket::dsl::*;
     diesel::insert_into(bucket)
         .values(bucket_item)
-        .returning((user_uuid, product_uuid, amount))
+        .on_conflict((user_uuid, product_uuid, option_uuids))
+        .do_update()
+        .set(amount.eq(amount + excluded(amount)))
+        .returning((user_uuid, product_uuid, amount, option_uuids))
         .get_result(db_conn)
         .await
 }
 
+pub async fn update_bucket_item_amount(
+    db_conn: &mut AsyncPgConnection,
+    us_uuid: Uuid,
+    prod_uuid: Uuid,
+    options: Vec<Uuid>,
+    new_amount: i16,
+) -> Result<Option<BucketItem>, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    diesel::update(
+        bucket
+            .filter(user_uuid.eq(us_uuid))
+            .filter(product_uuid.eq(prod_uuid))
+            .filter(option_uuids.eq(options)),
+    )
+    .set(amount.eq(new_amount))
+    .returning((user_uuid, product_uuid, amount, option_uuids))
+    .get_result(db_conn)
+    .await
+    .optional()
+}
+
 pub async fn select_bucket_items_by_uuid(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
+    uuid: Uuid,
+) -> Result<Vec<BucketItem>, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    bucket
+        .filter(user_uuid.eq(uuid))
+        .select((user_uuid, product_uuid, amount, option_uuids))
+        .get_results(db_conn)
+        .await
+}
+
+// Locks user's bucket rows until the end of current transaction
+pub async fn lock_bucket_items_by_uuid(
+    db_conn: &mut AsyncPgConnection,
     uuid: Uuid,
 ) -> Result<Vec<BucketItem>, Error> {
     use crate::schema::diesel_schema::bucket::dsl::*;
     bucket
         .filter(user_uuid.eq(uuid))
-        .select((user_uuid, product_uuid, amount))
+        .select((user_uuid, product_uuid, amount, option_uuids))
+        .for_update()
         .get_results(db_conn)
         .await
 }
 
+// Removes all lines of product when options are not set
 pub async fn delete_item_from_bucket(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     us_uuid: Uuid,
     prod_uuid: Uuid,
+    options: Option<Vec<Uuid>>,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    let mut query = diesel::delete(bucket)
+        .filter(user_uuid.eq(us_uuid).and(product_uuid.eq(prod_uuid)))
+        .into_boxed();
+    if let Some(options) = options {
+        query = query.filter(option_uuids.eq(options));
+    }
+    query.execute(db_conn).await
+}
+
+pub async fn delete_bucket_items_with_options(
+    db_conn: &mut AsyncPgConnection,
+    options: Vec<Uuid>,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    diesel::delete(bucket.filter(option_uuids.overlaps_with(options)))
+        .execute(db_conn)
+        .await
+}
+
+pub async fn delete_product_from_buckets(
+    db_conn: &mut AsyncPgConnection,
+    prod_uuid: Uuid,
 ) -> Result<usize, Error> {
     use crate::schema::diesel_schema::bucket::dsl::*;
-    diesel::delete(bucket.filter(user_uuid.eq(us_uuid).and(product_uuid.eq(prod_uuid))))
+    diesel::delete(bucket.filter(product_uuid.eq(prod_uuid)))
         .execute(db_conn)
         .await
 }
 
 pub async fn delete_items_from_user_bucket(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     us_uuid: Uuid,
 ) -> Result<usize, Error> {
     use crate::schema::diesel_schema::bucket::dsl::*;
@@ -123,28 +772,75 @@ pub async fn delete_items_from_user_bucket(
         .await
 }
 
+pub async fn count_bucket_items_updated_before(
+    db_conn: &mut AsyncPgConnection,
+    before: NaiveDateTime,
+) -> Result<i64, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    bucket
+        .filter(updated_at.lt(before))
+        .count()
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn delete_bucket_items_updated_before(
+    db_conn: &mut AsyncPgConnection,
+    before: NaiveDateTime,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::bucket::dsl::*;
+    diesel::delete(bucket.filter(updated_at.lt(before)))
+        .execute(db_conn)
+        .await
+}
+
 pub async fn create_order(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     new_order: CreateOrder,
 ) -> Result<OrderInfo, Error> {
     use crate::schema::diesel_schema::orders::dsl::*;
     diesel::insert_into(orders)
         .values(new_order)
-        .returning((
-            uuid,
-            user_uuid,
-            courier_uuid,
-            rating,
-            status,
-            updated_at,
-            address,
-        ))
+        .returning(ORDER_INFO_COLUMNS)
         .get_result(db_conn)
         .await
 }
 
+pub async fn create_checkout(
+    db_conn: &mut AsyncPgConnection,
+    new_checkout: CreateCheckout,
+) -> Result<Checkout, Error> {
+    diesel::insert_into(checkout::table)
+        .values(new_checkout)
+        .returning(checkout::all_columns)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn select_checkout(
+    db_conn: &mut AsyncPgConnection,
+    checkout_uuid: Uuid,
+) -> Result<Checkout, Error> {
+    checkout::table
+        .find(checkout_uuid)
+        .get_result(db_conn)
+        .await
+}
+
+pub async fn select_checkout_orders(
+    db_conn: &mut AsyncPgConnection,
+    uuid_checkout: Uuid,
+) -> Result<Vec<OrderInfo>, Error> {
+    orders::table
+        .filter(orders::checkout_uuid.eq(uuid_checkout))
+        .order((orders::created_at.asc(), orders::uuid.asc()))
+        .select(ORDER_INFO_COLUMNS)
+        .get_results(db_conn)
+        .await
+}
+
 pub async fn move_from_bucket_to_order(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     items: Vec<OrderItem>,
 ) -> Result<usize, Error> {
     use crate::schema::diesel_schema::order_item::dsl::*;
@@ -154,25 +850,54 @@ pub async fn move_from_bucket_to_order(
         .await
 }
 
-pub async fn select_order(db_conn: &mut DbConn<'_>, order_uuid: Uuid) -> Result<OrderInfo, Error> {
+pub async fn select_order(
+    db_conn: &mut AsyncPgConnection,
+    order_uuid: Uuid,
+) -> Result<OrderInfo, Error> {
     use crate::schema::diesel_schema::orders::dsl::*;
     orders
         .filter(uuid.eq(order_uuid))
-        .select((
-            uuid,
-            user_uuid,
-            courier_uuid,
-            rating,
-            status,
-            updated_at,
-            address,
-        ))
+        .select(ORDER_INFO_COLUMNS)
         .get_result(db_conn)
         .await
 }
 
+// Orders of user which wait for a free courier, oldest first.
+// Rows are locked until the end of current transaction
+pub async fn lock_orders_waiting_for_courier(
+    db_conn: &mut AsyncPgConnection,
+    uuid_user: Uuid,
+) -> Result<Vec<OrderInfo>, Error> {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    orders
+        .filter(user_uuid.eq(uuid_user))
+        .filter(status.eq(OrderStatus::WaitingForCourier))
+        .filter(courier_uuid.is_null())
+        .order((created_at.asc(), uuid.asc()))
+        .select(ORDER_INFO_COLUMNS)
+        .for_update()
+        .get_results(db_conn)
+        .await
+}
+
+// Courier is set only if order is still waiting for it
+pub async fn assign_courier_to_order(
+    db_conn: &mut AsyncPgConnection,
+    order_uuid: Uuid,
+    c_uuid: Uuid,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    diesel::update(orders)
+        .filter(uuid.eq(order_uuid))
+        .filter(status.eq(OrderStatus::WaitingForCourier))
+        .filter(courier_uuid.is_null())
+        .set((courier_uuid.eq(c_uuid), status.eq(OrderStatus::Assigned)))
+        .execute(db_conn)
+        .await
+}
+
 pub async fn update_order_rating(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     order_uuid: Uuid,
     new_rating: i16,
 ) -> Result<usize, Error> {
@@ -183,26 +908,53 @@ pub async fn update_order_rating(
         .execute(db_conn)
         .await
 }
+
+// Status is updated only if order still has expected status,
+// so concurrent transitions cannot overwrite each other
 pub async fn update_order_status(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     order_uuid: Uuid,
-    new_status: &str,
+    current_status: OrderStatus,
+    new_status: OrderStatus,
 ) -> Result<usize, Error> {
     use crate::schema::diesel_schema::orders::dsl::*;
     diesel::update(orders)
         .filter(uuid.eq(order_uuid))
+        .filter(status.eq(current_status))
         .set(status.eq(new_status))
         .execute(db_conn)
         .await
 }
+
+// "canceled_by_uuid" is None when order is canceled by the system
+pub async fn cancel_order(
+    db_conn: &mut AsyncPgConnection,
+    order_uuid: Uuid,
+    current_status: OrderStatus,
+    canceled_by_uuid: Option<Uuid>,
+    reason: String,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    diesel::update(orders)
+        .filter(uuid.eq(order_uuid))
+        .filter(status.eq(current_status))
+        .set((
+            status.eq(OrderStatus::Canceled),
+            canceled_by.eq(canceled_by_uuid),
+            cancel_reason.eq(reason),
+        ))
+        .execute(db_conn)
+        .await
+}
+
 pub async fn get_courier_rating(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     c_uuid: Uuid,
 ) -> Result<Vec<Option<i16>>, Error> {
     use crate::schema::diesel_schema::orders::dsl::*;
     orders
         .filter(courier_uuid.eq(c_uuid))
-        .filter(status.eq("FINISHED".to_string()))
+        .filter(status.eq(OrderStatus::Finished))
         .filter(rating.gt(0))
         .order(updated_at.desc())
         .limit(149)
@@ -211,53 +963,252 @@ pub async fn get_courier_rating(
         .await
 }
 
-pub async fn select_orders_by_filters(
-    db_conn: &mut DbConn<'_>,
-    order_uuid: Option<Uuid>,
-    uuid_courier: Option<Uuid>,
-    uuid_user: Option<Uuid>,
-    order_address: Option<String>,
-) -> Result<Vec<OrderInfo>, Error> {
+sql_function!(fn coalesce(x: Nullable<SmallInt>, y: SmallInt) -> SmallInt);
+
+fn filter_orders<'a>(filter: &OrderFilter) -> orders::BoxedQuery<'a, Pg> {
     use crate::schema::diesel_schema::orders::dsl::*;
     let mut query = orders.into_boxed();
 
-    if let Some(order_uuid) = order_uuid {
+    if let Some(order_uuid) = filter.order_uuid {
         query = query.filter(uuid.eq(order_uuid));
     }
 
-    if let Some(uuid_courier) = uuid_courier {
+    if let Some(uuid_courier) = filter.courier_uuid {
         query = query.filter(courier_uuid.eq(uuid_courier));
     }
 
-    if let Some(uuid_user) = uuid_user {
+    if let Some(uuid_user) = filter.user_uuid {
         query = query.filter(user_uuid.eq(uuid_user));
     }
 
-    if let Some(order_address) = order_address {
-        query = query.filter(address.eq(order_address));
+    if let Some(order_address) = &filter.address {
+        query = query.filter(address.eq(order_address.clone()));
+    }
+
+    if let Some(statuses) = &filter.statuses {
+        query = query.filter(status.eq_any(statuses.clone()));
+    }
+
+    if let Some(created_from) = filter.created_from {
+        query = query.filter(created_at.ge(created_from));
+    }
+
+    if let Some(created_to) = filter.created_to {
+        query = query.filter(created_at.le(created_to));
+    }
+
+    if let Some(updated_from) = filter.updated_from {
+        query = query.filter(updated_at.ge(updated_from));
+    }
+
+    if let Some(updated_to) = filter.updated_to {
+        query = query.filter(updated_at.le(updated_to));
+    }
+
+    if let Some(rating_from) = filter.rating_from {
+        query = query.filter(rating.ge(rating_from));
+    }
+
+    if let Some(rating_to) = filter.rating_to {
+        query = query.filter(rating.le(rating_to));
+    }
+
+    if let Some(order_restaurant) = filter.restaurant_uuid {
+        let restaurant_orders = order_item::table
+            .inner_join(product::table)
+            .filter(product::restaurant_uuid.eq(order_restaurant))
+            .select(order_item::order_uuid);
+        query = query.filter(uuid.eq_any(restaurant_orders));
     }
     query
-        .select((
-            uuid,
-            user_uuid,
-            courier_uuid,
-            rating,
-            status,
-            updated_at,
-            address,
-        ))
+}
+
+// Orders placed after (or before) cursor in (created_at, uuid) order
+fn orders_beyond_time_and_uuid(cursor: &OrderCursor, greater: bool) -> OrderCondition {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    if greater {
+        Box::new(
+            created_at
+                .gt(cursor.created_at)
+                .or(created_at.eq(cursor.created_at).and(uuid.gt(cursor.uuid))),
+        )
+    } else {
+        Box::new(
+            created_at
+                .lt(cursor.created_at)
+                .or(created_at.eq(cursor.created_at).and(uuid.lt(cursor.uuid))),
+        )
+    }
+}
+
+// Orders placed after (or before) cursor in pagination order
+fn orders_beyond_cursor(
+    cursor: &OrderCursor,
+    sort_by: OrderSortField,
+    direction: SortDirection,
+    after: bool,
+) -> OrderCondition {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    let greater = (direction == SortDirection::Asc) == after;
+    match sort_by {
+        OrderSortField::CreatedAt => orders_beyond_time_and_uuid(cursor, greater),
+        OrderSortField::Rating => {
+            let rating_beyond: OrderCondition = if greater {
+                Box::new(coalesce(rating, 0).gt(cursor.rating))
+            } else {
+                Box::new(coalesce(rating, 0).lt(cursor.rating))
+            };
+            Box::new(
+                rating_beyond.or(coalesce(rating, 0)
+                    .eq(cursor.rating)
+                    .and(orders_beyond_time_and_uuid(cursor, after))),
+            )
+        }
+    }
+}
+
+pub async fn count_orders(
+    db_conn: &mut AsyncPgConnection,
+    filter: &OrderFilter,
+) -> Result<i64, Error> {
+    filter_orders(filter).count().get_result(db_conn).await
+}
+
+pub async fn select_orders_page(
+    db_conn: &mut AsyncPgConnection,
+    filter: &OrderFilter,
+    page: &Page<OrderCursor>,
+) -> Result<Vec<OrderInfo>, Error> {
+    use crate::schema::diesel_schema::orders::dsl::*;
+    let mut query = filter_orders(filter);
+    let (sort_by, direction) = (filter.sort_by, filter.sort_direction);
+
+    if let Some(cursor) = &page.after {
+        query = query.filter(orders_beyond_cursor(cursor, sort_by, direction, true));
+    }
+
+    if let Some(cursor) = &page.before {
+        query = query.filter(orders_beyond_cursor(cursor, sort_by, direction, false));
+    }
+
+    // Ties in rating are broken by ascending (created_at, uuid)
+    let tie_direction = match sort_by {
+        OrderSortField::CreatedAt => direction,
+        OrderSortField::Rating => SortDirection::Asc,
+    };
+    let (direction, tie_direction) = if page.backward {
+        (direction.reverse(), tie_direction.reverse())
+    } else {
+        (direction, tie_direction)
+    };
+
+    if sort_by == OrderSortField::Rating {
+        query = match direction {
+            SortDirection::Asc => query.then_order_by(coalesce(rating, 0).asc()),
+            SortDirection::Desc => query.then_order_by(coalesce(rating, 0).desc()),
+        };
+    }
+
+    query = match tie_direction {
+        SortDirection::Asc => query.then_order_by((created_at.asc(), uuid.asc())),
+        SortDirection::Desc => query.then_order_by((created_at.desc(), uuid.desc())),
+    };
+
+    query
+        .select(ORDER_INFO_COLUMNS)
+        .limit(page.fetch_limit())
         .get_results(db_conn)
         .await
 }
 
 pub async fn select_order_items_by_uuid(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     uuid: Uuid,
 ) -> Result<Vec<OrderItem>, Error> {
     use crate::schema::diesel_schema::order_item::dsl::*;
     order_item
         .filter(order_uuid.eq(uuid))
-        .select((order_uuid, product_uuid, amount))
+        .select((
+            order_uuid,
+            product_uuid,
+            amount,
+            product_name,
+            unit_price,
+            option_uuids,
+            option_names,
+        ))
         .get_results(db_conn)
         .await
 }
+
+pub async fn create_order_status_event(
+    db_conn: &mut AsyncPgConnection,
+    event: CreateOrderStatusEvent,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::order_status_history::dsl::*;
+    diesel::insert_into(order_status_history)
+        .values(event)
+        .execute(db_conn)
+        .await
+}
+
+pub async fn select_order_status_events(
+    db_conn: &mut AsyncPgConnection,
+    uuid: Uuid,
+) -> Result<Vec<OrderStatusEvent>, Error> {
+    use crate::schema::diesel_schema::order_status_history::dsl::*;
+    order_status_history
+        .filter(order_uuid.eq(uuid))
+        .order((created_at.asc(), id.asc()))
+        .select((changed_by, from_status, to_status, note, created_at))
+        .get_results(db_conn)
+        .await
+}
+
+// Finds checkout created with idempotency key if key is not expired yet
+pub async fn select_checkout_by_idempotency_key(
+    db_conn: &mut AsyncPgConnection,
+    us_uuid: Uuid,
+    key: &str,
+    now: NaiveDateTime,
+) -> Result<Option<Checkout>, Error> {
+    use crate::schema::diesel_schema::order_idempotency_key::dsl::*;
+    order_idempotency_key
+        .inner_join(checkout::table)
+        .filter(user_uuid.eq(us_uuid))
+        .filter(idempotency_key.eq(key))
+        .filter(expires_at.gt(now))
+        .select(checkout::all_columns)
+        .get_result(db_conn)
+        .await
+        .optional()
+}
+
+pub async fn create_idempotency_key(
+    db_conn: &mut AsyncPgConnection,
+    key: CreateIdempotencyKey,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::order_idempotency_key::dsl::*;
+    diesel::insert_into(order_idempotency_key)
+        .values(key)
+        .execute(db_conn)
+        .await
+}
+
+// Expired keys can be reused by users
+pub async fn delete_expired_idempotency_key(
+    db_conn: &mut AsyncPgConnection,
+    us_uuid: Uuid,
+    key: &str,
+    now: NaiveDateTime,
+) -> Result<usize, Error> {
+    use crate::schema::diesel_schema::order_idempotency_key::dsl::*;
+    diesel::delete(
+        order_idempotency_key
+            .filter(user_uuid.eq(us_uuid))
+            .filter(idempotency_key.eq(key))
+            .filter(expires_at.le(now)),
+    )
+    .execute(db_conn)
+    .await
+}
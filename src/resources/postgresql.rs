@@ -1,6 +1,5 @@
 use async_graphql::Context;
 use bb8::RunError;
-use diesel::result::Error;
 use diesel_async::{
     pooled_connection::{bb8::Pool, AsyncDieselConnectionManager, PoolError},
     AsyncPgConnection,
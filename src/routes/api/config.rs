@@ -1,5 +1,8 @@
 use crate::{
-    routes::api::v1::graphql_routes::{graphiql, graphql_handler},
+    routes::api::v1::{
+        graphql_routes::{graphiql, graphql_handler},
+        metrics_routes::metrics_handler,
+    },
     utils::{configs::Config, graphql_utils::build_schema},
 };
 use async_graphql_axum::GraphQLSubscription;
@@ -12,6 +15,7 @@ pub fn api_v1_graphql_config(config: Config) -> IntoMakeService<Router> {
     let schema = build_schema(config.clone());
     Router::new()
         .route("/api/v1/graphql", get(graphiql).post(graphql_handler))
+        .route("/metrics", get(metrics_handler))
         .route_service("/ws", GraphQLSubscription::new(schema.clone()))
         .layer(Extension(schema))
         .layer(Extension(config))
@@ -1,9 +1,11 @@
 use crate::handlers::orders_handler::OrderServiceSchema;
 use crate::services::users_service::TokenClaims;
+use crate::utils::graphql_utils::{IdempotencyKey, IDEMPOTENCY_KEY_HEADER};
 use async_graphql::http::GraphiQLSource;
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use axum::debug_handler;
 use axum::extract::Extension;
+use axum::http::HeaderMap;
 use axum::response::{self, IntoResponse};
 
 pub async fn graphiql() -> impl IntoResponse {
@@ -19,10 +21,15 @@ pub async fn graphiql() -> impl IntoResponse {
 pub async fn graphql_handler(
     schema: Extension<OrderServiceSchema>,
     token_claims: TokenClaims,
+    headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    schema
-        .execute(req.into_inner().data(token_claims))
-        .await
-        .into()
+    let mut req = req.into_inner().data(token_claims);
+    if let Some(key) = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        req = req.data(IdempotencyKey(key.to_string()));
+    }
+    schema.execute(req).await.into()
 }
@@ -0,0 +1,7 @@
+use crate::utils::configs::Config;
+use axum::extract::Extension;
+
+// Exposes application counters in Prometheus text format
+pub async fn metrics_handler(Extension(config): Extension<Config>) -> String {
+    config.token_claims_cache.metrics()
+}
@@ -1 +1,2 @@
 pub mod graphql_routes;
+pub mod metrics_routes;
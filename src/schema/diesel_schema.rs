@@ -1,5 +1,11 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "order_status"))]
+    pub struct OrderStatus;
+}
+
 diesel::table! {
     bucket (id) {
         id -> Int8,
@@ -8,6 +14,62 @@ diesel::table! {
         amount -> Int2,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        option_uuids -> Array<Uuid>,
+    }
+}
+
+diesel::table! {
+    category (uuid) {
+        uuid -> Uuid,
+        parent_uuid -> Nullable<Uuid>,
+        name -> Text,
+        display_name -> Text,
+        position -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    checkout (uuid) {
+        uuid -> Uuid,
+        user_uuid -> Uuid,
+        address -> Text,
+        currency -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    modifier_group (uuid) {
+        uuid -> Uuid,
+        product_uuid -> Uuid,
+        name -> Text,
+        min_select -> Int4,
+        max_select -> Int4,
+        position -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    modifier_option (uuid) {
+        uuid -> Uuid,
+        group_uuid -> Uuid,
+        name -> Text,
+        price_delta -> Int8,
+        position -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_idempotency_key (user_uuid, idempotency_key) {
+        user_uuid -> Uuid,
+        idempotency_key -> Text,
+        checkout_uuid -> Uuid,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
     }
 }
 
@@ -19,19 +81,47 @@ diesel::table! {
         amount -> Int2,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        product_name -> Text,
+        unit_price -> Int8,
+        option_uuids -> Array<Uuid>,
+        option_names -> Array<Text>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::OrderStatus;
+
     orders (uuid) {
         uuid -> Uuid,
         user_uuid -> Uuid,
-        courier_uuid -> Uuid,
+        courier_uuid -> Nullable<Uuid>,
         rating -> Nullable<Int2>,
-        status -> Text,
+        status -> OrderStatus,
         created_at -> Timestamp,
         updated_at -> Timestamp,
         address -> Text,
+        canceled_by -> Nullable<Uuid>,
+        cancel_reason -> Nullable<Text>,
+        delivery_fee -> Int8,
+        currency -> Text,
+        checkout_uuid -> Uuid,
+        restaurant_uuid -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::OrderStatus;
+
+    order_status_history (id) {
+        id -> Int8,
+        order_uuid -> Uuid,
+        changed_by -> Nullable<Uuid>,
+        from_status -> Nullable<OrderStatus>,
+        to_status -> OrderStatus,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
 
@@ -39,14 +129,78 @@ diesel::table! {
     product (uuid) {
         uuid -> Uuid,
         name -> Text,
-        price -> Float8,
-        product_type -> Text,
-        restaurant -> Text,
+        price -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        currency -> Text,
+        restaurant_uuid -> Uuid,
+        category_uuid -> Uuid,
+        stock -> Nullable<Int4>,
+        is_available -> Bool,
+        archived_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    restaurant (uuid) {
+        uuid -> Uuid,
+        name -> Text,
+        address -> Nullable<Text>,
+        phone -> Nullable<Text>,
+        email -> Nullable<Text>,
+        time_zone -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        account_uuid -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    restaurant_holiday (restaurant_uuid, date) {
+        restaurant_uuid -> Uuid,
+        date -> Date,
+        opens_at -> Nullable<Time>,
+        closes_at -> Nullable<Time>,
+        note -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    restaurant_opening_hours (id) {
+        id -> Int8,
+        restaurant_uuid -> Uuid,
+        weekday -> Int2,
+        opens_at -> Time,
+        closes_at -> Time,
     }
 }
 
+diesel::joinable!(bucket -> product (product_uuid));
+diesel::joinable!(modifier_group -> product (product_uuid));
+diesel::joinable!(modifier_option -> modifier_group (group_uuid));
+diesel::joinable!(order_idempotency_key -> checkout (checkout_uuid));
 diesel::joinable!(order_item -> orders (order_uuid));
+diesel::joinable!(order_item -> product (product_uuid));
+diesel::joinable!(order_status_history -> orders (order_uuid));
+diesel::joinable!(orders -> checkout (checkout_uuid));
+diesel::joinable!(orders -> restaurant (restaurant_uuid));
+diesel::joinable!(product -> category (category_uuid));
+diesel::joinable!(product -> restaurant (restaurant_uuid));
+diesel::joinable!(restaurant_holiday -> restaurant (restaurant_uuid));
+diesel::joinable!(restaurant_opening_hours -> restaurant (restaurant_uuid));
 
-diesel::allow_tables_to_appear_in_same_query!(bucket, order_item, orders, product,);
+diesel::allow_tables_to_appear_in_same_query!(
+    bucket,
+    category,
+    checkout,
+    modifier_group,
+    modifier_option,
+    order_idempotency_key,
+    order_item,
+    order_status_history,
+    orders,
+    product,
+    restaurant,
+    restaurant_holiday,
+    restaurant_opening_hours,
+);
@@ -1,20 +1,37 @@
 use crate::handlers::orders_handler;
-use crate::models::orders_model::{BucketItem, OrderInfo, OrderItem, OrderQueueInfo, ProductInfo};
+use crate::models::orders_model::{
+    BucketItem, BucketSummary, Category, CategoryNode, Checkout, FlatCategory, ModifierGroup,
+    ModifierOption, Money, OrderConnection, OrderFilter, OrderInfo, OrderItem, OrderQueueInfo,
+    OrderStatus, OrderStatusEvent, ProductConnection, ProductFilter, ProductInfo, ReorderReport,
+    Restaurant, RestaurantInput, SortDirection, UpdateRestaurantInput,
+};
 use crate::utils::simple_broker::SimpleBroker;
 use async_graphql::futures_util::Stream;
-use async_graphql::{Context, FieldResult, MergedObject, Object, Subscription};
+use async_graphql::{Context, FieldResult, MaybeUndefined, MergedObject, Object, Subscription};
 use futures_util::StreamExt;
 use uuid::Uuid;
 
 #[derive(MergedObject, Default)]
-pub struct QueryRoot(Products, Bucket, Orders);
+pub struct QueryRoot(Products, Categories, Restaurants, Bucket, Orders);
 
 #[derive(MergedObject, Default)]
-pub struct MutationRoot(ProductsMutation, BucketMutation, OrdersMutation);
+pub struct MutationRoot(
+    ProductsMutation,
+    CategoriesMutation,
+    RestaurantsMutation,
+    BucketMutation,
+    OrdersMutation,
+);
 
 #[derive(Default)]
 pub struct Products;
 
+#[derive(Default)]
+pub struct Categories;
+
+#[derive(Default)]
+pub struct Restaurants;
+
 #[derive(Default)]
 pub struct Orders;
 
@@ -24,6 +41,12 @@ pub struct Bucket;
 #[derive(Default)]
 pub struct ProductsMutation;
 
+#[derive(Default)]
+pub struct CategoriesMutation;
+
+#[derive(Default)]
+pub struct RestaurantsMutation;
+
 #[derive(Default)]
 pub struct OrdersMutation;
 
@@ -48,26 +71,179 @@ impl Products {
             .await
     }
 
-    // Get all products
+    // Search products by name, type and restaurant, tolerant to typos
+    // results are ranked by relevance, "first" limits their number
+    pub async fn search_products<'a>(
+        &self,
+        context: &Context<'a>,
+        query: String,
+        first: Option<i32>,
+    ) -> FieldResult<Vec<ProductInfo>> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .search_products(context, query, first)
+            .await
+    }
+
+    // Get products page
+    // optional filters: "name", "category_uuid" (with nested categories), "restaurant_uuid"
+    // optional sorting by price: "price_from_cheap", "price_from_expensive"
+    // unavailable and out of stock products are hidden with "only_available"
+    // paginated with "first"/"after" or "last"/"before"
+    #[allow(clippy::too_many_arguments)]
     pub async fn products<'a>(
         &self,
         context: &Context<'a>,
         name: Option<String>,
         price_from_cheap: Option<bool>,
         price_from_expensive: Option<bool>,
-        product_type: Option<String>,
-        restaurant: Option<String>,
-    ) -> FieldResult<Vec<ProductInfo>> {
+        category_uuid: Option<Uuid>,
+        restaurant_uuid: Option<Uuid>,
+        #[graphql(default)] only_available: bool,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<ProductConnection> {
+        let price_order = match (price_from_cheap, price_from_expensive) {
+            (Some(true), _) => Some(SortDirection::Asc),
+            (_, Some(true)) => Some(SortDirection::Desc),
+            _ => None,
+        };
+        let filter = ProductFilter {
+            name,
+            category_uuids: category_uuid.map(|uuid| vec![uuid]),
+            restaurant_uuid,
+            price_order,
+            only_available,
+        };
         context
             .data_unchecked::<orders_handler::Products>()
-            .products(
-                context,
-                name,
-                price_from_cheap,
-                price_from_expensive,
-                product_type,
-                restaurant,
-            )
+            .products(context, filter, after, before, first, last)
+            .await
+    }
+}
+
+#[Object]
+impl Categories {
+    // Get category by uuid
+    // "uuid" required
+    pub async fn category<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of category")] uuid: Uuid,
+    ) -> FieldResult<Category> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .category(context, uuid)
+            .await
+    }
+
+    // Get tree of categories ordered by position
+    // Nesting is limited by query depth, use categoryList for deep trees
+    pub async fn categories<'a>(&self, context: &Context<'a>) -> FieldResult<Vec<CategoryNode>> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .categories(context)
+            .await
+    }
+
+    // Get all categories as flat list in tree order
+    // Every category has "parent_uuid" and "depth" to assemble tree of any depth
+    pub async fn category_list<'a>(&self, context: &Context<'a>) -> FieldResult<Vec<FlatCategory>> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .category_list(context)
+            .await
+    }
+}
+
+#[Object]
+impl CategoriesMutation {
+    // Creating new category
+    // "name", "display_name" required, top level category when "parent_uuid" is not set
+    pub async fn create_category<'a>(
+        &self,
+        context: &Context<'a>,
+        name: String,
+        display_name: String,
+        parent_uuid: Option<Uuid>,
+        #[graphql(default)] position: i32,
+    ) -> FieldResult<Category> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .create_category(context, name, display_name, parent_uuid, position)
+            .await
+    }
+
+    // Updating category info
+    // "uuid" required, "parent_uuid": null moves category to top level
+    pub async fn update_category<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of category")] uuid: Uuid,
+        name: Option<String>,
+        display_name: Option<String>,
+        parent_uuid: MaybeUndefined<Uuid>,
+        position: Option<i32>,
+    ) -> FieldResult<Category> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .update_category(context, uuid, name, display_name, parent_uuid, position)
+            .await
+    }
+}
+
+#[Object]
+impl Restaurants {
+    // Get restaurant by uuid
+    // "uuid" required
+    pub async fn restaurant<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of restaurant")] uuid: Uuid,
+    ) -> FieldResult<Restaurant> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .restaurant(context, uuid)
+            .await
+    }
+
+    // Get all restaurants ordered by name
+    pub async fn restaurants<'a>(&self, context: &Context<'a>) -> FieldResult<Vec<Restaurant>> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .restaurants(context)
+            .await
+    }
+}
+
+#[Object]
+impl RestaurantsMutation {
+    // Creating new restaurant with opening hours and holidays
+    // "name" required
+    pub async fn create_restaurant<'a>(
+        &self,
+        context: &Context<'a>,
+        input: RestaurantInput,
+    ) -> FieldResult<Restaurant> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .create_restaurant(context, input)
+            .await
+    }
+
+    // Updating restaurant info
+    // "uuid" required, opening hours and holidays are replaced when set
+    pub async fn update_restaurant<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of restaurant")] uuid: Uuid,
+        input: UpdateRestaurantInput,
+    ) -> FieldResult<Restaurant> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .update_restaurant(context, uuid, input)
             .await
     }
 }
@@ -75,36 +251,144 @@ impl Products {
 #[Object]
 impl ProductsMutation {
     // Creating new product
-    // "name", "price", "product type", "restaurant" required
+    // "name", "price", "category_uuid", "restaurant_uuid" required
     pub async fn create_product<'a>(
         &self,
         context: &Context<'a>,
         name: String,
-        price: f64,
-        product_type: String,
-        restaurant: String,
+        price: Money,
+        category_uuid: Uuid,
+        restaurant_uuid: Uuid,
     ) -> FieldResult<String> {
         context
             .data_unchecked::<orders_handler::Products>()
-            .create_product(context, name, price, product_type, restaurant)
+            .create_product(context, name, price, category_uuid, restaurant_uuid)
+            .await
+    }
+
+    // Adjusting product stock and availability, for admins and restaurant staff
+    // "product_uuid" required, "delta" changes stock, "stock" sets it, null disables tracking
+    pub async fn adjust_stock<'a>(
+        &self,
+        context: &Context<'a>,
+        product_uuid: Uuid,
+        delta: Option<i32>,
+        stock: MaybeUndefined<i32>,
+        is_available: Option<bool>,
+    ) -> FieldResult<ProductInfo> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .adjust_stock(context, product_uuid, delta, stock, is_available)
+            .await
+    }
+
+    // Creating modifier group of product, like size or extras
+    // "product_uuid", "name" required
+    pub async fn create_modifier_group<'a>(
+        &self,
+        context: &Context<'a>,
+        product_uuid: Uuid,
+        name: String,
+        #[graphql(default)] min_select: i32,
+        #[graphql(default = 1)] max_select: i32,
+        #[graphql(default)] position: i32,
+    ) -> FieldResult<ModifierGroup> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .create_modifier_group(
+                context,
+                product_uuid,
+                name,
+                min_select,
+                max_select,
+                position,
+            )
+            .await
+    }
+
+    // Creating option of modifier group
+    // "group_uuid", "name" required
+    pub async fn create_modifier_option<'a>(
+        &self,
+        context: &Context<'a>,
+        group_uuid: Uuid,
+        name: String,
+        #[graphql(default)] price_delta: Money,
+        #[graphql(default)] position: i32,
+    ) -> FieldResult<ModifierOption> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .create_modifier_option(context, group_uuid, name, price_delta, position)
+            .await
+    }
+
+    // Deleting modifier group with its options
+    // "uuid" required
+    pub async fn delete_modifier_group<'a>(
+        &self,
+        context: &Context<'a>,
+        uuid: Uuid,
+    ) -> FieldResult<String> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .delete_modifier_group(context, uuid)
+            .await
+    }
+
+    // Deleting modifier option
+    // "uuid" required
+    pub async fn delete_modifier_option<'a>(
+        &self,
+        context: &Context<'a>,
+        uuid: Uuid,
+    ) -> FieldResult<String> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .delete_modifier_option(context, uuid)
+            .await
+    }
+
+    // Archiving product, it stays available for order history
+    // "uuid" required
+    pub async fn archive_product<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of product")] uuid: Uuid,
+    ) -> FieldResult<ProductInfo> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .archive_product(context, uuid)
+            .await
+    }
+
+    // Restoring archived product
+    // "uuid" required
+    pub async fn restore_product<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of product")] uuid: Uuid,
+    ) -> FieldResult<ProductInfo> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .restore_product(context, uuid)
             .await
     }
 
     // Updating product info
     // "uuid" required
-    // "name", "price", " product type", "restaurant" optional
+    // "name", "price", "category_uuid", "restaurant_uuid" optional
     pub async fn update_product<'a>(
         &self,
         context: &Context<'a>,
         name: Option<String>,
-        price: Option<f64>,
-        product_type: Option<String>,
-        restaurant: Option<String>,
+        price: Option<Money>,
+        category_uuid: Option<Uuid>,
+        restaurant_uuid: Option<Uuid>,
         #[graphql(desc = "uuid of product")] uuid: Uuid,
     ) -> FieldResult<ProductInfo> {
         context
             .data_unchecked::<orders_handler::Products>()
-            .update_product(context, name, price, product_type, restaurant, uuid)
+            .update_product(context, name, price, category_uuid, restaurant_uuid, uuid)
             .await
     }
 }
@@ -123,20 +407,35 @@ impl Orders {
             .await
     }
 
+    // Get checkout with its orders, one per restaurant
+    // "uuid" required
+    pub async fn checkout<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of checkout")] uuid: Uuid,
+    ) -> FieldResult<Checkout> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .checkout(context, uuid)
+            .await
+    }
+
     // Get orders info with filters
-    // optional filters: "order_uuid", "courier_uuid", "user_uuid", "address"
-    // geting all orders if no filters provided
+    // filters by uuids, address, statuses, created/updated date ranges,
+    // rating range and restaurant, sorted by creation time or rating
+    // paginated with "first"/"after" or "last"/"before"
     pub async fn filter_orders<'a>(
         &self,
         context: &Context<'a>,
-        order_uuid: Option<Uuid>,
-        courier_uuid: Option<Uuid>,
-        user_uuid: Option<Uuid>,
-        address: Option<String>,
-    ) -> FieldResult<Vec<OrderInfo>> {
+        #[graphql(default)] filter: OrderFilter,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<OrderConnection> {
         context
             .data_unchecked::<orders_handler::Orders>()
-            .filter_orders(context, order_uuid, courier_uuid, user_uuid, address)
+            .filter_orders(context, filter, after, before, first, last)
             .await
     }
 
@@ -153,24 +452,74 @@ impl Orders {
             .get_order_items(context, uuid)
             .await
     }
+
+    // Get order status history
+    // "uuid" required
+    // Show all status changes of order ordered by time
+    pub async fn order_timeline<'a>(
+        &self,
+        context: &Context<'a>,
+        #[graphql(desc = "uuid of order")] uuid: Uuid,
+    ) -> FieldResult<Vec<OrderStatusEvent>> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .get_order_timeline(context, uuid)
+            .await
+    }
 }
 
 #[Object]
 impl OrdersMutation {
-    // Creating new order
+    // Creating order from user's bucket with products of one restaurant
     // "user_uuid", "address" required
+    // "idempotency_key" optional, can be passed with "Idempotency-Key" header as well
     pub async fn create_order<'a>(
         &self,
         context: &Context<'a>,
         user_uuid: Uuid,
         address: String,
+        idempotency_key: Option<String>,
     ) -> FieldResult<OrderInfo> {
         context
             .data_unchecked::<orders_handler::Orders>()
-            .create_order(context, user_uuid, address)
+            .create_order(context, user_uuid, address, idempotency_key)
+            .await
+    }
+
+    // Creating checkout with one order per restaurant from user's bucket
+    // "user_uuid", "address" required
+    // "idempotency_key" optional, can be passed with "Idempotency-Key" header as well
+    pub async fn create_checkout<'a>(
+        &self,
+        context: &Context<'a>,
+        user_uuid: Uuid,
+        address: String,
+        idempotency_key: Option<String>,
+    ) -> FieldResult<Checkout> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .create_checkout(context, user_uuid, address, idempotency_key)
             .await
     }
 
+    // Copy items of finished order into user's bucket
+    // "order_uuid" required, only the user who placed the order can reorder it
+    // Archived and out of stock items are skipped
+    // Not idempotent, repeated request adds items again up to bucket item limit
+    pub async fn reorder<'a>(
+        &self,
+        context: &Context<'a>,
+        order_uuid: Uuid,
+    ) -> FieldResult<ReorderReport> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .reorder(context, order_uuid)
+            .await
+    }
+
+    // Rate delivery of order, the rating updates courier's rating
+    // "order_uuid", "rating" required
+    // Only the user who placed the order can rate it, once
     pub async fn estimate_delivery<'a>(
         &self,
         context: &Context<'a>,
@@ -183,7 +532,10 @@ impl OrdersMutation {
             .await
     }
 
-    pub async fn complete_delivery<'a>(
+    // Finish delivery of order and report it to analytics service
+    // "order_uuid" required
+    // Available only for order courier
+    pub async fn complete_delivery(
         &self,
         context: &Context<'_>,
         order_uuid: Uuid,
@@ -193,6 +545,50 @@ impl OrdersMutation {
             .complete_delivery(context, order_uuid)
             .await
     }
+
+    // Status of user in queue for a free courier and average waiting time
+    // "user_uuid" required
+    pub async fn wait_for_free_courier<'a>(
+        &self,
+        context: &Context<'a>,
+        user_uuid: Uuid,
+    ) -> FieldResult<OrderQueueInfo> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .wait_for_free_courier(context, user_uuid)
+            .await
+    }
+
+    // Move order to the next delivery stage
+    // "order_uuid", "status" required
+    // Available only for order courier, "PICKED_UP" and "IN_PROGRESS" statuses
+    pub async fn update_order_status<'a>(
+        &self,
+        context: &Context<'a>,
+        order_uuid: Uuid,
+        status: OrderStatus,
+    ) -> FieldResult<OrderInfo> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .update_order_status(context, order_uuid, status)
+            .await
+    }
+
+    // Cancel order with reason
+    // "order_uuid", "reason" required
+    // Users can cancel only their own orders during cancellation time, admins at any time
+    // Repeated cancellation by the same user succeeds, so it is safe to retry
+    pub async fn cancel_order<'a>(
+        &self,
+        context: &Context<'a>,
+        order_uuid: Uuid,
+        reason: String,
+    ) -> FieldResult<String> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .cancel_order(context, order_uuid, reason)
+            .await
+    }
 }
 
 #[Object]
@@ -210,36 +606,71 @@ impl Bucket {
             .get_bucket_items(context, uuid)
             .await
     }
+
+    // Get user bucket with prices and totals
+    // "user_uuid" required
+    // Warnings show what would prevent creating an order from the bucket
+    pub async fn bucket<'a>(
+        &self,
+        context: &Context<'a>,
+        user_uuid: Uuid,
+    ) -> FieldResult<BucketSummary> {
+        context
+            .data_unchecked::<orders_handler::Buckets>()
+            .bucket_summary(context, user_uuid)
+            .await
+    }
 }
 
 #[Object]
 impl BucketMutation {
-    // Add product with qty to user's bucket
+    // Add product with qty to user's bucket, increments existing line
     // "user_uuid", "product_uuid", "amount" required
+    // "option_uuids" are validated against product modifier groups
+    // Not idempotent, clients retrying requests should use updateBucketItemAmount
     pub async fn add_to_bucket<'a>(
         &self,
         context: &Context<'a>,
         user_uuid: Uuid,
         product_uuid: Uuid,
         amount: i16,
+        #[graphql(default)] option_uuids: Vec<Uuid>,
     ) -> FieldResult<BucketItem> {
         context
             .data_unchecked::<orders_handler::Buckets>()
-            .add_to_bucket(context, user_uuid, product_uuid, amount)
+            .add_to_bucket(context, user_uuid, product_uuid, amount, option_uuids)
+            .await
+    }
+
+    // Set amount of product with options in user's bucket, 0 removes it
+    // "user_uuid", "product_uuid", "amount" required
+    pub async fn update_bucket_item_amount<'a>(
+        &self,
+        context: &Context<'a>,
+        user_uuid: Uuid,
+        product_uuid: Uuid,
+        #[graphql(default)] option_uuids: Vec<Uuid>,
+        amount: i16,
+    ) -> FieldResult<Option<BucketItem>> {
+        context
+            .data_unchecked::<orders_handler::Buckets>()
+            .update_bucket_item_amount(context, user_uuid, product_uuid, option_uuids, amount)
             .await
     }
 
     // Remove product from user's bucket
     // "user_uuid", "product_uuid" required
+    // removes only the line with "option_uuids" when set, all lines of product otherwise
     pub async fn remove_from_bucket<'a>(
         &self,
         context: &Context<'a>,
         user_uuid: Uuid,
         product_uuid: Uuid,
+        option_uuids: Option<Vec<Uuid>>,
     ) -> FieldResult<String> {
         context
             .data_unchecked::<orders_handler::Buckets>()
-            .remove_from_bucket(context, user_uuid, product_uuid)
+            .remove_from_bucket(context, user_uuid, product_uuid, option_uuids)
             .await
     }
 
@@ -255,17 +686,6 @@ impl BucketMutation {
             .clear_bucket(context, user_uuid)
             .await
     }
-
-    pub async fn wait_for_free_courier<'a>(
-        &self,
-        context: &Context<'a>,
-        user_uuid: Uuid,
-    ) -> FieldResult<OrderQueueInfo> {
-        context
-            .data_unchecked::<orders_handler::Orders>()
-            .wait_for_free_courier(context, user_uuid)
-            .await
-    }
 }
 
 /////////////////////////////////////////////////////////
@@ -307,9 +727,7 @@ impl SubscriptionRoot {
     }
 }
 
-use async_graphql::{Enum, Result, ID};
-use futures_util::lock::Mutex;
-use slab::Slab;
+use async_graphql::Enum;
 
 #[derive(Enum, Eq, PartialEq, Copy, Clone)]
 pub enum MutationType {
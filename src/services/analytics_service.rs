@@ -0,0 +1,268 @@
+use crate::{
+    repository::orders_repository,
+    resources::postgresql::DbPool,
+    utils::{
+        configs::Config,
+        grpc::analytics_grpc::{analytics_client::AnalyticsClient, OrderItems, SaveOrderRequest},
+    },
+};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tonic::{transport::Channel, Code};
+use uuid::Uuid;
+
+// Upper bound of delay between retries of analytics report
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// Reports finished order to analytics service in background,
+// so courier doesn't wait for analytics service.
+// Failed requests are retried with doubling delay.
+// Pending reports are kept only in memory and are lost on restart
+pub fn report_finished_order(config: &Config, order_uuid: Uuid, finished_at: DateTime<Utc>) {
+    let db_pool = config.db_pool.clone();
+    let channel = config.analytics_channel.clone();
+    let attempts = config.analytics_retry_attempts;
+    let delay = Duration::from_secs(config.analytics_retry_delay);
+    tokio::spawn(async move {
+        let request = match build_save_order_request(&db_pool, order_uuid, finished_at).await {
+            Ok(request) => request,
+            Err(error) => {
+                tracing::error!(
+                    order_uuid = %order_uuid,
+                    error.message = %error,
+                    "Cannot build analytics report of finished order"
+                );
+                return;
+            }
+        };
+        send_with_retry(channel, request, attempts, delay).await;
+    });
+}
+
+async fn build_save_order_request(
+    db_pool: &DbPool,
+    order_uuid: Uuid,
+    finished_at: DateTime<Utc>,
+) -> Result<SaveOrderRequest, anyhow::Error> {
+    let mut db_conn = db_pool.get().await?;
+    let items = orders_repository::select_order_items_by_uuid(&mut db_conn, order_uuid).await?;
+    Ok(SaveOrderRequest {
+        order_uuid: order_uuid.to_string(),
+        finished_at: finished_at.to_rfc3339(),
+        order_items: items
+            .into_iter()
+            .map(|item| OrderItems {
+                product_uuid: item.product_uuid.to_string(),
+                amount: item.amount.into(),
+            })
+            .collect(),
+    })
+}
+
+// At least one request is sent even if attempts are 0.
+// Report is dropped after the last failed attempt
+async fn send_with_retry(
+    channel: Channel,
+    request: SaveOrderRequest,
+    attempts: u32,
+    mut delay: Duration,
+) {
+    let mut client = AnalyticsClient::new(channel);
+    let attempts = attempts.max(1);
+    for attempt in 1..=attempts {
+        match client.save_order_info(request.clone()).await {
+            Ok(response) => {
+                if !response.into_inner().record_created {
+                    // analytics service already has this order
+                    tracing::warn!(order_uuid = %request.order_uuid, "Order was not saved by analytics service");
+                }
+                return;
+            }
+            Err(status) if is_retryable(status.code()) && attempt < attempts => {
+                tracing::warn!(
+                    order_uuid = %request.order_uuid,
+                    attempt,
+                    grpc.code = ?status.code(),
+                    error.message = %status.message(),
+                    "Analytics service request failed, retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay = next_delay(delay);
+            }
+            Err(status) => {
+                tracing::error!(
+                    order_uuid = %request.order_uuid,
+                    attempt,
+                    grpc.code = ?status.code(),
+                    error.message = %status.message(),
+                    "Cannot report finished order to analytics service"
+                );
+                return;
+            }
+        }
+    }
+}
+
+// Doubled delay, limited by MAX_RETRY_DELAY
+fn next_delay(delay: Duration) -> Duration {
+    delay.saturating_mul(2).min(MAX_RETRY_DELAY)
+}
+
+// Request with invalid data fails the same way every time
+fn is_retryable(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+            | Code::Aborted
+            | Code::Internal
+            | Code::Unknown
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::grpc::analytics_grpc::analytics_server::{Analytics, AnalyticsServer};
+    use crate::utils::grpc::analytics_grpc::{SaveOrderResponse, SaveRegRequest, SaveRegResponse};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Endpoint, Server};
+    use tonic::{Request, Response, Status};
+
+    // Fails first "fail_first" reports like MOCK_ANALYTICS_FAIL_FIRST of mock server
+    struct StubAnalytics {
+        fail_first: u32,
+        received: Arc<AtomicU32>,
+    }
+
+    #[tonic::async_trait]
+    impl Analytics for StubAnalytics {
+        async fn save_reg_info(
+            &self,
+            _request: Request<SaveRegRequest>,
+        ) -> Result<Response<SaveRegResponse>, Status> {
+            Err(Status::unimplemented("Not used by orders service"))
+        }
+
+        async fn save_order_info(
+            &self,
+            _request: Request<SaveOrderRequest>,
+        ) -> Result<Response<SaveOrderResponse>, Status> {
+            let number = self.received.fetch_add(1, Ordering::SeqCst) + 1;
+            if number <= self.fail_first {
+                return Err(Status::unavailable("Stub analytics is unavailable"));
+            }
+            Ok(Response::new(SaveOrderResponse {
+                record_created: true,
+            }))
+        }
+    }
+
+    // Returns channel to stub on random port and counter of received reports
+    async fn start_stub(fail_first: u32) -> (Channel, Arc<AtomicU32>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let received = Arc::new(AtomicU32::new(0));
+        let service = StubAnalytics {
+            fail_first,
+            received: received.clone(),
+        };
+        tokio::spawn(
+            Server::builder()
+                .add_service(AnalyticsServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let channel = Endpoint::from_shared(format!("http://{}", address))
+            .unwrap()
+            .connect_lazy();
+        (channel, received)
+    }
+
+    // Sends report with logs written into returned buffer
+    async fn send_report(channel: Channel, attempts: u32) -> String {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || LogWriter(writer.clone()))
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = SaveOrderRequest {
+            order_uuid: Uuid::new_v4().to_string(),
+            finished_at: Utc::now().to_rfc3339(),
+            order_items: Vec::new(),
+        };
+        send_with_retry(channel, request, attempts, Duration::from_millis(1)).await;
+        let logs = logs.lock().unwrap();
+        String::from_utf8_lossy(&logs).into_owned()
+    }
+
+    struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn report_is_retried_until_saved() {
+        let (channel, received) = start_stub(2).await;
+
+        let logs = send_report(channel, 5).await;
+
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+        assert_eq!(logs.matches("retrying").count(), 2);
+        assert!(!logs.contains("Cannot report finished order"));
+    }
+
+    #[tokio::test]
+    async fn report_is_dropped_after_last_attempt() {
+        let (channel, received) = start_stub(u32::MAX).await;
+
+        let logs = send_report(channel, 3).await;
+
+        assert_eq!(received.load(Ordering::SeqCst), 3);
+        assert_eq!(logs.matches("retrying").count(), 2);
+        assert!(logs.contains("Cannot report finished order to analytics service"));
+    }
+
+    #[tokio::test]
+    async fn at_least_one_attempt_is_made() {
+        let (channel, received) = start_stub(u32::MAX).await;
+
+        let logs = send_report(channel, 0).await;
+
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+        assert!(logs.contains("Cannot report finished order to analytics service"));
+    }
+
+    #[test]
+    fn delay_is_doubled() {
+        assert_eq!(next_delay(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(next_delay(Duration::from_secs(8)), Duration::from_secs(16));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        assert_eq!(next_delay(Duration::from_secs(40)), MAX_RETRY_DELAY);
+        assert_eq!(next_delay(Duration::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        assert!(is_retryable(Code::Unavailable));
+        assert!(is_retryable(Code::DeadlineExceeded));
+        assert!(!is_retryable(Code::InvalidArgument));
+        assert!(!is_retryable(Code::AlreadyExists));
+    }
+}
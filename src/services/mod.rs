@@ -1,2 +1,3 @@
+pub mod analytics_service;
 pub mod orders_service;
 pub mod users_service;
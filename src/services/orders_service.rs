@@ -1,22 +1,35 @@
 use crate::schema::graphql_schema::{CourierStatus, MutationType};
+use crate::services::users_service::{find_free_courier, release_courier};
 use crate::utils::grpc::orders_grpc::orders_server::Orders;
 use crate::utils::grpc::orders_grpc::{
-    CourierForUserRequest, CourierForUserResponse, TimeExpirationRequest, TimeExpirationResponse,
+    CourierForUserRequest, CourierForUserResponse, RevokeTokenRequest, RevokeTokenResponse,
+    TimeExpirationRequest, TimeExpirationResponse,
 };
 use crate::utils::simple_broker::SimpleBroker;
+use crate::utils::token_claims_cache::TokenClaimsCache;
 use crate::{
     handlers::orders_handler,
-    models::orders_model::{BucketItem, OrderInfo, OrderItem, OrderQueueInfo, ProductInfo},
-    repository::orders_repository::select_bucket_items_by_uuid,
-    resources::postgresql::DbConn,
-    utils::{
-        configs::Config,
-        graphql_utils::{has_access, policy_from_context},
+    models::orders_model::{
+        BucketItem, BucketLine, BucketSummary, BucketWarning, BucketWarningCode, Category,
+        CategoryNode, Checkout, CreateCheckout, CreateIdempotencyKey, CreateOrder,
+        CreateOrderStatusEvent, FlatCategory, Holiday, HolidayInput, ModifierGroup, ModifierOption,
+        Money, MoneyOverflowError, OpeningHours, OpeningHoursInput, OrderInfo, OrderItem,
+        OrderQueueInfo, OrderStatus, OrderStatusEvent, ProductFilter, ProductInfo, ReorderReport,
+        ReorderSkipReason, Restaurant, SkippedOrderItem,
     },
+    repository::orders_repository::{self, select_bucket_items_by_uuid},
+    resources::postgresql::DbPool,
+    utils::{configs::Config, errors::OrderStatusError},
 };
-use async_graphql::{Context, FieldResult, Object};
-use chrono::Utc;
-use tonic::{Code, Request, Response, Status};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{Context, Error, ErrorExtensions, FieldResult, Object};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::{transport::Channel, Request, Response, Status};
 use uuid::Uuid;
 
 #[Object]
@@ -27,23 +40,232 @@ impl ProductInfo {
     async fn name(&self) -> String {
         self.name.clone()
     }
-    async fn price(&self) -> f64 {
+    async fn price(&self) -> Money {
         self.price
     }
+    async fn currency(&self) -> String {
+        self.currency.clone()
+    }
+    async fn stock(&self) -> Option<i32> {
+        self.stock
+    }
+    // Product can be ordered: it is not archived, disabled or out of stock
+    async fn is_available(&self) -> bool {
+        self.archived_at.is_none() && self.is_available && self.stock != Some(0)
+    }
+    async fn archived_at(&self) -> Option<NaiveDateTime> {
+        self.archived_at
+    }
+    async fn modifier_groups(&self, context: &Context<'_>) -> FieldResult<Vec<ModifierGroup>> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .modifier_groups(context, self.uuid)
+            .await
+    }
+    async fn category_uuid(&self) -> Uuid {
+        self.category_uuid
+    }
+    async fn category(&self, context: &Context<'_>) -> FieldResult<Category> {
+        context
+            .data_unchecked::<orders_handler::Categories>()
+            .category(context, self.category_uuid)
+            .await
+    }
+    async fn restaurant_uuid(&self) -> Uuid {
+        self.restaurant_uuid
+    }
+    async fn restaurant(&self, context: &Context<'_>) -> FieldResult<Restaurant> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .restaurant(context, self.restaurant_uuid)
+            .await
+    }
+}
 
-    // Example of secured field.
-    // Available only for admins and analysts
-    async fn product_type(&self, context: &Context<'_>) -> FieldResult<String> {
-        let policy = policy_from_context(context)?;
-        if has_access(&policy.admin_policy, context) {
-            Ok(self.product_type.clone())
-        } else {
-            Ok("Forbidden".to_string())
-        }
+#[Object]
+impl ModifierGroup {
+    async fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+    async fn min_select(&self) -> i32 {
+        self.min_select
+    }
+    async fn max_select(&self) -> i32 {
+        self.max_select
+    }
+    async fn position(&self) -> i32 {
+        self.position
+    }
+    async fn options(&self, context: &Context<'_>) -> FieldResult<Vec<ModifierOption>> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .modifier_options(context, self.uuid)
+            .await
+    }
+}
+
+#[Object]
+impl ModifierOption {
+    async fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+    async fn group_uuid(&self) -> Uuid {
+        self.group_uuid
+    }
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+    async fn price_delta(&self) -> Money {
+        self.price_delta
+    }
+    async fn position(&self) -> i32 {
+        self.position
+    }
+}
+
+#[Object]
+impl Category {
+    async fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+    async fn parent_uuid(&self) -> Option<Uuid> {
+        self.parent_uuid
+    }
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+    async fn display_name(&self) -> String {
+        self.display_name.clone()
+    }
+    async fn position(&self) -> i32 {
+        self.position
+    }
+}
+
+#[Object]
+impl CategoryNode {
+    async fn uuid(&self) -> &Uuid {
+        &self.category.uuid
     }
+    async fn parent_uuid(&self) -> Option<Uuid> {
+        self.category.parent_uuid
+    }
+    async fn name(&self) -> String {
+        self.category.name.clone()
+    }
+    async fn display_name(&self) -> String {
+        self.category.display_name.clone()
+    }
+    async fn position(&self) -> i32 {
+        self.category.position
+    }
+    async fn children(&self) -> &Vec<CategoryNode> {
+        &self.children
+    }
+}
+
+#[Object]
+impl FlatCategory {
+    async fn uuid(&self) -> &Uuid {
+        &self.category.uuid
+    }
+    async fn parent_uuid(&self) -> Option<Uuid> {
+        self.category.parent_uuid
+    }
+    async fn name(&self) -> String {
+        self.category.name.clone()
+    }
+    async fn display_name(&self) -> String {
+        self.category.display_name.clone()
+    }
+    async fn position(&self) -> i32 {
+        self.category.position
+    }
+    async fn depth(&self) -> i32 {
+        self.depth
+    }
+}
+
+#[Object]
+impl Restaurant {
+    async fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+    async fn address(&self) -> Option<String> {
+        self.address.clone()
+    }
+    async fn phone(&self) -> Option<String> {
+        self.phone.clone()
+    }
+    async fn email(&self) -> Option<String> {
+        self.email.clone()
+    }
+    async fn time_zone(&self) -> String {
+        self.time_zone.clone()
+    }
+    async fn account_uuid(&self) -> Option<Uuid> {
+        self.account_uuid
+    }
+    async fn opening_hours(&self, context: &Context<'_>) -> FieldResult<Vec<OpeningHours>> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .opening_hours(context, self.uuid)
+            .await
+    }
+    async fn holidays(&self, context: &Context<'_>) -> FieldResult<Vec<Holiday>> {
+        context
+            .data_unchecked::<orders_handler::Restaurants>()
+            .holidays(context, self.uuid)
+            .await
+    }
+    // Schedules of listed restaurants are loaded in one batch
+    async fn is_open(&self, context: &Context<'_>) -> FieldResult<bool> {
+        let schedule = context
+            .data_unchecked::<DataLoader<RestaurantScheduleLoader>>()
+            .load_one(self.uuid)
+            .await?
+            .unwrap_or_default();
+        Ok(is_restaurant_open_at(
+            self,
+            &schedule.opening_hours,
+            &schedule.holidays,
+            Utc::now(),
+        ))
+    }
+}
+
+#[Object]
+impl OpeningHours {
+    async fn weekday(&self) -> i16 {
+        self.weekday
+    }
+    async fn opens_at(&self) -> NaiveTime {
+        self.opens_at
+    }
+    async fn closes_at(&self) -> NaiveTime {
+        self.closes_at
+    }
+}
 
-    async fn restaurant(&self) -> String {
-        self.restaurant.clone()
+#[Object]
+impl Holiday {
+    async fn date(&self) -> NaiveDate {
+        self.date
+    }
+    async fn opens_at(&self) -> Option<NaiveTime> {
+        self.opens_at
+    }
+    async fn closes_at(&self) -> Option<NaiveTime> {
+        self.closes_at
+    }
+    async fn note(&self) -> Option<String> {
+        self.note.clone()
     }
 }
 
@@ -52,6 +274,15 @@ impl BucketItem {
     async fn amount(&self) -> i16 {
         self.amount
     }
+    async fn option_uuids(&self) -> &Vec<Uuid> {
+        &self.option_uuids
+    }
+    async fn options(&self, context: &Context<'_>) -> FieldResult<Vec<ModifierOption>> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .modifier_options_by_uuids(context, self.option_uuids.clone())
+            .await
+    }
     async fn product(&self, context: &Context<'_>) -> FieldResult<ProductInfo> {
         context
             .data_unchecked::<orders_handler::Products>()
@@ -60,32 +291,209 @@ impl BucketItem {
     }
 }
 
+#[Object]
+impl BucketLine {
+    async fn product_uuid(&self) -> Uuid {
+        self.product_uuid
+    }
+    async fn product_name(&self) -> String {
+        self.product_name.clone()
+    }
+    async fn amount(&self) -> i16 {
+        self.amount
+    }
+    async fn option_uuids(&self) -> &Vec<Uuid> {
+        &self.option_uuids
+    }
+    async fn option_names(&self) -> &Vec<String> {
+        &self.option_names
+    }
+    async fn unit_price(&self) -> Money {
+        self.unit_price
+    }
+    async fn line_price(&self) -> FieldResult<Money> {
+        Ok(self.unit_price.times(self.amount)?)
+    }
+    async fn product(&self, context: &Context<'_>) -> FieldResult<ProductInfo> {
+        context
+            .data_unchecked::<orders_handler::Products>()
+            .product(context, self.product_uuid)
+            .await
+    }
+}
+
+#[Object]
+impl BucketWarning {
+    async fn code(&self) -> BucketWarningCode {
+        self.code
+    }
+    async fn message(&self) -> String {
+        self.message.clone()
+    }
+    async fn product_uuid(&self) -> Option<Uuid> {
+        self.product_uuid
+    }
+    async fn restaurant_uuid(&self) -> Option<Uuid> {
+        self.restaurant_uuid
+    }
+}
+
+#[Object]
+impl BucketSummary {
+    async fn user_uuid(&self) -> Uuid {
+        self.user_uuid
+    }
+    async fn lines(&self) -> &Vec<BucketLine> {
+        &self.lines
+    }
+    async fn subtotal(&self) -> Money {
+        self.subtotal
+    }
+    // Estimated with current settings, one fee for every restaurant in bucket.
+    // Actual fee is stored on orders
+    async fn delivery_fee(&self) -> Money {
+        self.delivery_fee
+    }
+    async fn total(&self) -> FieldResult<Money> {
+        Ok(self.subtotal.checked_add(self.delivery_fee)?)
+    }
+    async fn currency(&self) -> String {
+        self.currency.clone()
+    }
+    // Empty when bucket can be ordered right now
+    async fn warnings(&self) -> &Vec<BucketWarning> {
+        &self.warnings
+    }
+}
+
+#[Object]
+impl SkippedOrderItem {
+    async fn item(&self) -> &OrderItem {
+        &self.item
+    }
+    async fn reason(&self) -> ReorderSkipReason {
+        self.reason
+    }
+}
+
+#[Object]
+impl ReorderReport {
+    async fn added(&self) -> &Vec<BucketItem> {
+        &self.added
+    }
+    async fn skipped(&self) -> &Vec<SkippedOrderItem> {
+        &self.skipped
+    }
+}
+
+#[Object]
+impl Checkout {
+    async fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+    async fn user_uuid(&self) -> Uuid {
+        self.user_uuid
+    }
+    async fn address(&self) -> String {
+        self.address.clone()
+    }
+    async fn currency(&self) -> String {
+        self.currency.clone()
+    }
+    async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    // One order per restaurant, each delivered by its own courier
+    async fn orders(&self, context: &Context<'_>) -> FieldResult<Vec<OrderInfo>> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .get_checkout_orders(context, self.uuid)
+            .await
+    }
+}
+
 #[Object]
 impl OrderInfo {
     async fn uuid(&self) -> &Uuid {
         &self.uuid
     }
+    async fn checkout_uuid(&self) -> Uuid {
+        self.checkout_uuid
+    }
     async fn user_uuid(&self) -> Uuid {
         self.user_uuid
     }
-    async fn courier_uuid(&self) -> Uuid {
+    async fn courier_uuid(&self) -> Option<Uuid> {
         self.courier_uuid
     }
+    async fn restaurant_uuid(&self) -> Option<Uuid> {
+        self.restaurant_uuid
+    }
     async fn rating(&self) -> Option<i16> {
         self.rating
     }
-    async fn status(&self) -> String {
-        self.status.clone()
+    async fn status(&self) -> OrderStatus {
+        self.status
     }
     async fn address(&self) -> String {
         self.address.clone()
     }
+    async fn subtotal(&self, context: &Context<'_>) -> FieldResult<Money> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .get_order_subtotal(context, self.uuid)
+            .await
+    }
+    async fn delivery_fee(&self) -> Money {
+        self.delivery_fee
+    }
+    async fn total(&self, context: &Context<'_>) -> FieldResult<Money> {
+        let subtotal = context
+            .data_unchecked::<orders_handler::Orders>()
+            .get_order_subtotal(context, self.uuid)
+            .await?;
+        Ok(subtotal.checked_add(self.delivery_fee)?)
+    }
+    async fn currency(&self) -> String {
+        self.currency.clone()
+    }
+    async fn canceled_by(&self) -> Option<Uuid> {
+        self.canceled_by
+    }
+    async fn cancel_reason(&self) -> Option<String> {
+        self.cancel_reason.clone()
+    }
     async fn items(&self, context: &Context<'_>) -> FieldResult<Vec<OrderItem>> {
         context
             .data_unchecked::<orders_handler::Orders>()
             .get_order_items(context, self.uuid)
             .await
     }
+    async fn timeline(&self, context: &Context<'_>) -> FieldResult<Vec<OrderStatusEvent>> {
+        context
+            .data_unchecked::<orders_handler::Orders>()
+            .get_order_timeline(context, self.uuid)
+            .await
+    }
+}
+
+#[Object]
+impl OrderStatusEvent {
+    async fn changed_by(&self) -> Option<Uuid> {
+        self.changed_by
+    }
+    async fn from_status(&self) -> Option<OrderStatus> {
+        self.from_status
+    }
+    async fn to_status(&self) -> OrderStatus {
+        self.to_status
+    }
+    async fn note(&self) -> Option<String> {
+        self.note.clone()
+    }
+    async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
 }
 
 #[Object]
@@ -93,6 +501,21 @@ impl OrderItem {
     async fn amount(&self) -> i16 {
         self.amount
     }
+    async fn product_name(&self) -> String {
+        self.product_name.clone()
+    }
+    async fn unit_price(&self) -> Money {
+        self.unit_price
+    }
+    async fn option_uuids(&self) -> &Vec<Uuid> {
+        &self.option_uuids
+    }
+    async fn option_names(&self) -> &Vec<String> {
+        &self.option_names
+    }
+    async fn line_price(&self) -> FieldResult<Money> {
+        Ok(self.unit_price.times(self.amount)?)
+    }
     async fn product(&self, context: &Context<'_>) -> FieldResult<ProductInfo> {
         context
             .data_unchecked::<orders_handler::Products>()
@@ -111,15 +534,754 @@ impl OrderQueueInfo {
     }
 }
 
-pub async fn check_bucket(
-    db_conn: &mut DbConn<'_>,
+// Prices bucket with current prices and collects everything
+// that would prevent it from being ordered right now
+pub async fn summarize_bucket(
+    db_conn: &mut AsyncPgConnection,
+    config: &Config,
     user_uuid: Uuid,
-) -> FieldResult<Vec<BucketItem>> {
+) -> FieldResult<BucketSummary> {
     let items = select_bucket_items_by_uuid(db_conn, user_uuid).await?;
+    let product_uuids = items.iter().map(|item| item.product_uuid).collect();
+    let products = orders_repository::select_products_by_uuids(db_conn, product_uuids).await?;
+
+    let mut lines = Vec::with_capacity(items.len());
+    let mut warnings = Vec::new();
     if items.is_empty() {
+        warnings.push(BucketWarning {
+            code: BucketWarningCode::EmptyBucket,
+            message: "Empty bucket".to_string(),
+            product_uuid: None,
+            restaurant_uuid: None,
+        });
+    }
+    for (index, item) in items.iter().enumerate() {
+        let product = match products
+            .iter()
+            .find(|product| product.uuid == item.product_uuid)
+        {
+            Some(product) => product,
+            None => {
+                warnings.push(BucketWarning {
+                    code: BucketWarningCode::ProductNotFound,
+                    message: "Product not found".to_string(),
+                    product_uuid: Some(item.product_uuid),
+                    restaurant_uuid: None,
+                });
+                continue;
+            }
+        };
+        // product can be in several lines with different options,
+        // availability is checked once for all of them
+        let first_line = !items[..index]
+            .iter()
+            .any(|other| other.product_uuid == item.product_uuid);
+        if first_line {
+            let requested: i32 = items
+                .iter()
+                .filter(|other| other.product_uuid == item.product_uuid)
+                .map(|other| i32::from(other.amount))
+                .sum();
+            if let Some(warning) = product_warning(product, requested, &config.currency) {
+                warnings.push(warning);
+            }
+        }
+
+        let mut line = BucketLine {
+            product_uuid: product.uuid,
+            product_name: product.name.clone(),
+            amount: item.amount,
+            option_uuids: item.option_uuids.clone(),
+            option_names: Vec::new(),
+            unit_price: product.price,
+        };
+        match select_valid_options(db_conn, product.uuid, &item.option_uuids).await {
+            Ok(options) => {
+                line.unit_price = options.iter().try_fold(line.unit_price, |price, option| {
+                    price.checked_add(option.price_delta)
+                })?;
+                line.option_names = options.into_iter().map(|option| option.name).collect();
+            }
+            Err(error) => warnings.push(BucketWarning {
+                code: BucketWarningCode::InvalidOptions,
+                message: format!("{}: {}", product.name, error.message),
+                product_uuid: Some(product.uuid),
+                restaurant_uuid: None,
+            }),
+        }
+        lines.push(line);
+    }
+
+    let restaurants = orders_repository::select_bucket_restaurants(db_conn, user_uuid).await?;
+    // checkout creates one order with its own delivery fee per restaurant
+    let restaurant_count = i16::try_from(restaurants.len()).map_err(|_| MoneyOverflowError)?;
+    let delivery_fee = config.delivery_fee.times(restaurant_count)?;
+    for restaurant in restaurants {
+        if !is_restaurant_open_now(db_conn, &restaurant).await? {
+            warnings.push(BucketWarning {
+                code: BucketWarningCode::RestaurantClosed,
+                message: format!("Restaurant {} is closed", restaurant.name),
+                product_uuid: None,
+                restaurant_uuid: Some(restaurant.uuid),
+            });
+        }
+    }
+
+    let subtotal = Money::total(
+        lines
+            .iter()
+            .map(|line| line.unit_price.times(line.amount))
+            .collect::<Result<Vec<_>, _>>()?,
+    )?;
+    if !lines.is_empty() && subtotal < config.min_order_amount {
+        warnings.push(BucketWarning {
+            code: BucketWarningCode::BelowMinimumOrder,
+            message: format!(
+                "Minimum order amount is {} {}",
+                config.min_order_amount, config.currency
+            ),
+            product_uuid: None,
+            restaurant_uuid: None,
+        });
+    }
+
+    Ok(BucketSummary {
+        user_uuid,
+        lines,
+        subtotal,
+        delivery_fee,
+        currency: config.currency.clone(),
+        warnings,
+    })
+}
+
+// Mirrors conditions of stock reservation at checkout
+fn product_warning(product: &ProductInfo, requested: i32, currency: &str) -> Option<BucketWarning> {
+    let (code, message) = if product.archived_at.is_some() {
+        (
+            BucketWarningCode::ProductArchived,
+            format!("Product {} is archived", product.name),
+        )
+    } else if !product.is_available || product.stock.is_some_and(|stock| stock < requested) {
+        (
+            BucketWarningCode::ProductUnavailable,
+            format!("Product {} is not available", product.name),
+        )
+    } else if product.currency != currency {
+        (
+            BucketWarningCode::CurrencyMismatch,
+            format!("Product {} has different currency", product.name),
+        )
+    } else {
+        return None;
+    };
+    Some(BucketWarning {
+        code,
+        message,
+        product_uuid: Some(product.uuid),
+        restaurant_uuid: None,
+    })
+}
+
+// Bucket can be ordered only when its summary has no warnings
+pub async fn check_bucket(
+    db_conn: &mut AsyncPgConnection,
+    config: &Config,
+    user_uuid: Uuid,
+) -> FieldResult<BucketSummary> {
+    let summary = summarize_bucket(db_conn, config, user_uuid).await?;
+    if let Some(warning) = summary.warnings.first() {
+        return Err(warning.message.as_str().into());
+    }
+    Ok(summary)
+}
+
+// Builds category forest from categories ordered by position
+pub fn build_category_tree(
+    categories: &[Category],
+    parent_uuid: Option<Uuid>,
+) -> Vec<CategoryNode> {
+    categories
+        .iter()
+        .filter(|category| category.parent_uuid == parent_uuid)
+        .map(|category| CategoryNode {
+            category: category.clone(),
+            children: build_category_tree(categories, Some(category.uuid)),
+        })
+        .collect()
+}
+
+// Categories ordered as depth-first walk of the tree,
+// every category follows its parent and precedes its next sibling
+pub fn flatten_category_tree(categories: &[Category]) -> Vec<FlatCategory> {
+    fn walk(nodes: Vec<CategoryNode>, depth: i32, flat: &mut Vec<FlatCategory>) {
+        for node in nodes {
+            flat.push(FlatCategory {
+                category: node.category,
+                depth,
+            });
+            walk(node.children, depth + 1, flat);
+        }
+    }
+    let mut flat = Vec::with_capacity(categories.len());
+    walk(build_category_tree(categories, None), 0, &mut flat);
+    flat
+}
+
+// Category itself and all of its nested children
+pub fn category_with_descendants(categories: &[Category], category_uuid: Uuid) -> Vec<Uuid> {
+    let mut found = vec![category_uuid];
+    let mut index = 0;
+    while index < found.len() {
+        let parent_uuid = found[index];
+        let children: Vec<Uuid> = categories
+            .iter()
+            .filter(|category| category.parent_uuid == Some(parent_uuid))
+            .map(|category| category.uuid)
+            .filter(|uuid| !found.contains(uuid))
+            .collect();
+        found.extend(children);
+        index += 1;
+    }
+    found
+}
+
+// Expands filtered categories with their nested children
+pub async fn with_descendant_categories(
+    db_conn: &mut AsyncPgConnection,
+    mut filter: ProductFilter,
+) -> FieldResult<ProductFilter> {
+    if let Some(category_uuids) = &filter.category_uuids {
+        let categories = orders_repository::select_categories(db_conn).await?;
+        let mut expanded = Vec::new();
+        for category_uuid in category_uuids {
+            expanded.extend(category_with_descendants(&categories, *category_uuid));
+        }
+        filter.category_uuids = Some(expanded);
+    }
+    Ok(filter)
+}
+
+pub fn validate_restaurant_schedule(
+    time_zone: Option<&str>,
+    opening_hours: Option<&[OpeningHoursInput]>,
+    holidays: Option<&[HolidayInput]>,
+) -> FieldResult<()> {
+    if let Some(time_zone) = time_zone {
+        time_zone
+            .parse::<Tz>()
+            .map_err(|_| format!("Unknown time zone {}", time_zone))?;
+    }
+    for hours in opening_hours.unwrap_or_default() {
+        if !(1..=7).contains(&hours.weekday) {
+            return Err("Weekday must be between 1 (Monday) and 7 (Sunday)".into());
+        }
+        if hours.opens_at >= hours.closes_at {
+            return Err("Opening time must be before closing time".into());
+        }
+    }
+    for holiday in holidays.unwrap_or_default() {
+        match (holiday.opens_at, holiday.closes_at) {
+            (None, None) => {}
+            (Some(opens_at), Some(closes_at)) if opens_at < closes_at => {}
+            _ => return Err(format!("Invalid holiday hours on {}", holiday.date).into()),
+        }
+    }
+    Ok(())
+}
+
+// Local time of restaurant, unknown time zone is treated as UTC
+fn restaurant_local_time(restaurant: &Restaurant, now: DateTime<Utc>) -> NaiveDateTime {
+    let time_zone = restaurant.time_zone.parse::<Tz>().unwrap_or(Tz::UTC);
+    now.with_timezone(&time_zone).naive_local()
+}
+
+// Holiday on local date overrides weekly hours.
+// Restaurant without weekly hours is considered always open
+pub fn is_restaurant_open_at(
+    restaurant: &Restaurant,
+    opening_hours: &[OpeningHours],
+    holidays: &[Holiday],
+    now: DateTime<Utc>,
+) -> bool {
+    let local = restaurant_local_time(restaurant, now);
+    let time = local.time();
+    let holiday = holidays
+        .iter()
+        .find(|holiday| holiday.restaurant_uuid == restaurant.uuid && holiday.date == local.date());
+    if let Some(holiday) = holiday {
+        return match (holiday.opens_at, holiday.closes_at) {
+            (Some(opens_at), Some(closes_at)) => opens_at <= time && time < closes_at,
+            _ => false,
+        };
+    }
+    if opening_hours.is_empty() {
+        return true;
+    }
+    let weekday = local.weekday().number_from_monday() as i16;
+    opening_hours
+        .iter()
+        .any(|hours| hours.weekday == weekday && hours.opens_at <= time && time < hours.closes_at)
+}
+
+pub async fn is_restaurant_open_now(
+    db_conn: &mut AsyncPgConnection,
+    restaurant: &Restaurant,
+) -> FieldResult<bool> {
+    let now = Utc::now();
+    let local_date = restaurant_local_time(restaurant, now).date();
+    let opening_hours = orders_repository::select_opening_hours(db_conn, restaurant.uuid).await?;
+    let holiday = orders_repository::select_holiday(db_conn, restaurant.uuid, local_date).await?;
+    Ok(is_restaurant_open_at(
+        restaurant,
+        &opening_hours,
+        holiday.as_slice(),
+        now,
+    ))
+}
+
+#[derive(Default, Clone)]
+pub struct RestaurantSchedule {
+    pub opening_hours: Vec<OpeningHours>,
+    // holidays around current date, local date of any time zone is among them
+    pub holidays: Vec<Holiday>,
+}
+
+// Loads opening hours and holidays of several restaurants at once,
+// so listing restaurants with "isOpen" doesn't query them one by one
+pub struct RestaurantScheduleLoader {
+    pub db_pool: DbPool,
+}
+
+#[tonic::async_trait]
+impl Loader<Uuid> for RestaurantScheduleLoader {
+    type Value = RestaurantSchedule;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut db_conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|error| Arc::new(error.into()))?;
+        let today = Utc::now().date_naive();
+        let opening_hours =
+            orders_repository::select_restaurants_opening_hours(&mut db_conn, keys.to_vec())
+                .await
+                .map_err(|error| Arc::new(error.into()))?;
+        let holidays = orders_repository::select_restaurants_holidays_between(
+            &mut db_conn,
+            keys.to_vec(),
+            today - Duration::days(1),
+            today + Duration::days(1),
+        )
+        .await
+        .map_err(|error| Arc::new(error.into()))?;
+
+        let mut schedules: HashMap<Uuid, RestaurantSchedule> = keys
+            .iter()
+            .map(|uuid| (*uuid, RestaurantSchedule::default()))
+            .collect();
+        for (restaurant_uuid, hours) in opening_hours {
+            if let Some(schedule) = schedules.get_mut(&restaurant_uuid) {
+                schedule.opening_hours.push(hours);
+            }
+        }
+        for holiday in holidays {
+            if let Some(schedule) = schedules.get_mut(&holiday.restaurant_uuid) {
+                schedule.holidays.push(holiday);
+            }
+        }
+        Ok(schedules)
+    }
+}
+
+// Checks selected options against modifier groups of product.
+// Returns selected options in display order
+pub async fn select_valid_options(
+    db_conn: &mut AsyncPgConnection,
+    product_uuid: Uuid,
+    selected: &[Uuid],
+) -> FieldResult<Vec<ModifierOption>> {
+    let groups = orders_repository::select_modifier_groups(db_conn, product_uuid).await?;
+    let options = orders_repository::select_product_modifier_options(db_conn, product_uuid).await?;
+    validate_options(&groups, options, selected)
+}
+
+// Checks selected options against modifier groups and options of product
+fn validate_options(
+    groups: &[ModifierGroup],
+    options: Vec<ModifierOption>,
+    selected: &[Uuid],
+) -> FieldResult<Vec<ModifierOption>> {
+    for (index, option_uuid) in selected.iter().enumerate() {
+        if selected[..index].contains(option_uuid) {
+            return Err(format!("Option {} is selected more than once", option_uuid).into());
+        }
+        if !options.iter().any(|option| option.uuid == *option_uuid) {
+            return Err(format!("Option {} is not available for this product", option_uuid).into());
+        }
+    }
+    let selected_options: Vec<ModifierOption> = options
+        .into_iter()
+        .filter(|option| selected.contains(&option.uuid))
+        .collect();
+    for group in groups {
+        let count = selected_options
+            .iter()
+            .filter(|option| option.group_uuid == group.uuid)
+            .count() as i32;
+        if count < group.min_select || count > group.max_select {
+            return Err(format!(
+                "Select from {} to {} options in {}",
+                group.min_select, group.max_select, group.name
+            )
+            .into());
+        }
+    }
+    Ok(selected_options)
+}
+
+// Creates checkout with one order per restaurant and moves user's bucket into them.
+// "couriers" are reserved couriers by restaurant uuid, one for every restaurant in bucket.
+// Items are captured with current prices for every order,
+// stock is reserved only for orders which got a courier.
+// Restaurant without courier gets an order waiting for courier.
+// Should be called inside transaction, so bucket and waiting orders are locked
+pub async fn create_checkout_from_bucket(
+    db_conn: &mut AsyncPgConnection,
+    new_checkout: CreateCheckout,
+    couriers: &[(Uuid, Option<Uuid>)],
+    delivery_fee: Money,
+) -> FieldResult<(Checkout, Vec<OrderInfo>)> {
+    let user_uuid = new_checkout.user_uuid;
+    let waiting = orders_repository::lock_orders_waiting_for_courier(db_conn, user_uuid).await?;
+    if !waiting.is_empty() {
+        return Err("Previous order is still waiting for courier".into());
+    }
+    let bucket = orders_repository::lock_bucket_items_by_uuid(db_conn, user_uuid).await?;
+    if bucket.is_empty() {
         return Err("Empty bucket".into());
     }
-    Ok(items)
+    let product_uuids = bucket.iter().map(|item| item.product_uuid).collect();
+    let products = orders_repository::select_products_by_uuids(db_conn, product_uuids).await?;
+    let mut items = Vec::with_capacity(bucket.len());
+    for item in bucket {
+        let product = products
+            .iter()
+            .find(|product| product.uuid == item.product_uuid)
+            .ok_or("Product not found")?;
+        if product.currency != new_checkout.currency {
+            return Err(format!("Product {} has different currency", product.name).into());
+        }
+        if !couriers
+            .iter()
+            .any(|(restaurant_uuid, _)| *restaurant_uuid == product.restaurant_uuid)
+        {
+            // product from another restaurant was added after couriers were found
+            return Err("Bucket has changed, please try again".into());
+        }
+        items.push((item, product));
+    }
+
+    let checkout = orders_repository::create_checkout(db_conn, new_checkout).await?;
+    let mut orders = Vec::with_capacity(couriers.len());
+    for (restaurant_uuid, courier_uuid) in couriers {
+        let restaurant_items: Vec<(BucketItem, &ProductInfo)> = items
+            .iter()
+            .filter(|(_, product)| product.restaurant_uuid == *restaurant_uuid)
+            .map(|(item, product)| (item.clone(), *product))
+            .collect();
+        if restaurant_items.is_empty() {
+            return Err("Bucket has changed, please try again".into());
+        }
+        let status = match courier_uuid {
+            Some(_) => OrderStatus::Assigned,
+            None => OrderStatus::WaitingForCourier,
+        };
+        let new_order = CreateOrder {
+            checkout_uuid: checkout.uuid,
+            restaurant_uuid: Some(*restaurant_uuid),
+            user_uuid,
+            courier_uuid: *courier_uuid,
+            status,
+            address: checkout.address.clone(),
+            delivery_fee,
+            currency: checkout.currency.clone(),
+        };
+        let order = orders_repository::create_order(db_conn, new_order).await?;
+        let event = CreateOrderStatusEvent {
+            order_uuid: order.uuid,
+            changed_by: Some(user_uuid),
+            from_status: None,
+            to_status: order.status,
+            note: None,
+        };
+        orders_repository::create_order_status_event(db_conn, event).await?;
+        let order_items = add_items_to_order(db_conn, order.uuid, restaurant_items).await?;
+        if courier_uuid.is_some() {
+            if let Some(item) = reserve_order_stock(db_conn, &order_items).await? {
+                return Err(format!("Product {} is not available", item.product_name).into());
+            }
+        }
+        orders.push(order);
+    }
+    orders_repository::delete_items_from_user_bucket(db_conn, user_uuid).await?;
+    Ok((checkout, orders))
+}
+
+// Adds items to order with prices and options captured from products
+async fn add_items_to_order(
+    db_conn: &mut AsyncPgConnection,
+    order_uuid: Uuid,
+    items: Vec<(BucketItem, &ProductInfo)>,
+) -> FieldResult<Vec<OrderItem>> {
+    let mut order_items = Vec::with_capacity(items.len());
+    for (item, product) in items {
+        // modifier rules could change since the item was added
+        let options = select_valid_options(db_conn, product.uuid, &item.option_uuids).await?;
+        order_items.push(OrderItem {
+            order_uuid,
+            product_uuid: item.product_uuid,
+            amount: item.amount,
+            product_name: product.name.clone(),
+            unit_price: options.iter().try_fold(product.price, |price, option| {
+                price.checked_add(option.price_delta)
+            })?,
+            option_uuids: item.option_uuids,
+            option_names: options.into_iter().map(|option| option.name).collect(),
+        });
+    }
+    orders_repository::move_from_bucket_to_order(db_conn, order_items.clone()).await?;
+    Ok(order_items)
+}
+
+// Reserves stock of products for order which got a courier.
+// Returns item whose product is not available,
+// stock reserved for previous items is returned then
+async fn reserve_order_stock<'a>(
+    db_conn: &mut AsyncPgConnection,
+    items: &'a [OrderItem],
+) -> FieldResult<Option<&'a OrderItem>> {
+    for (index, item) in items.iter().enumerate() {
+        let reserved = orders_repository::reserve_product_stock(
+            db_conn,
+            item.product_uuid,
+            item.amount.into(),
+        )
+        .await?;
+        if reserved == 0 {
+            for reserved_item in &items[..index] {
+                orders_repository::release_product_stock(
+                    db_conn,
+                    reserved_item.product_uuid,
+                    reserved_item.amount.into(),
+                )
+                .await?;
+            }
+            return Ok(Some(item));
+        }
+    }
+    Ok(None)
+}
+
+// Gives found courier to the oldest order of user waiting for courier
+// and reserves stock of its items.
+// Waiting orders with products which are not available anymore are canceled.
+// Returns None if user has no order to deliver, the courier has to be released then.
+// Should be called inside transaction
+pub async fn assign_courier_to_waiting_order(
+    db_conn: &mut AsyncPgConnection,
+    user_uuid: Uuid,
+    courier_uuid: Uuid,
+) -> FieldResult<Option<OrderInfo>> {
+    let waiting = orders_repository::lock_orders_waiting_for_courier(db_conn, user_uuid).await?;
+    for order in waiting {
+        let items = orders_repository::select_order_items_by_uuid(db_conn, order.uuid).await?;
+        if let Some(item) = reserve_order_stock(db_conn, &items).await? {
+            let reason = format!("Product {} is not available anymore", item.product_name);
+            let canceled = orders_repository::cancel_order(
+                db_conn,
+                order.uuid,
+                OrderStatus::WaitingForCourier,
+                None,
+                reason.clone(),
+            )
+            .await?;
+            if canceled > 0 {
+                let event = CreateOrderStatusEvent {
+                    order_uuid: order.uuid,
+                    changed_by: None,
+                    from_status: Some(OrderStatus::WaitingForCourier),
+                    to_status: OrderStatus::Canceled,
+                    note: Some(reason),
+                };
+                orders_repository::create_order_status_event(db_conn, event).await?;
+            }
+            continue;
+        }
+
+        let assigned =
+            orders_repository::assign_courier_to_order(db_conn, order.uuid, courier_uuid).await?;
+        if assigned == 0 {
+            return Err(OrderStatusError::Conflict {
+                expected: OrderStatus::WaitingForCourier,
+            }
+            .extend());
+        }
+        let event = CreateOrderStatusEvent {
+            order_uuid: order.uuid,
+            changed_by: None,
+            from_status: Some(OrderStatus::WaitingForCourier),
+            to_status: OrderStatus::Assigned,
+            note: None,
+        };
+        orders_repository::create_order_status_event(db_conn, event).await?;
+        let order = orders_repository::select_order(db_conn, order.uuid).await?;
+        return Ok(Some(order));
+    }
+    Ok(None)
+}
+
+// Orders of user waiting for courier are expired when users service
+// stops searching for a courier. Their stock was not reserved yet
+pub async fn expire_waiting_orders(
+    db_conn: &mut AsyncPgConnection,
+    user_uuid: Uuid,
+) -> FieldResult<Vec<OrderInfo>> {
+    let waiting = orders_repository::lock_orders_waiting_for_courier(db_conn, user_uuid).await?;
+    for order in &waiting {
+        change_order_status(db_conn, order, OrderStatus::Expired, None).await?;
+    }
+    Ok(waiting)
+}
+
+// Removes bucket lines which were not changed during ttl.
+// In dry run mode only counts them
+pub async fn expire_abandoned_buckets(
+    db_conn: &mut AsyncPgConnection,
+    ttl: i32,
+    dry_run: bool,
+) -> Result<usize, diesel::result::Error> {
+    let before = Utc::now().naive_utc() - Duration::seconds(ttl as i64);
+    if dry_run {
+        let count = orders_repository::count_bucket_items_updated_before(db_conn, before).await?;
+        return Ok(count as usize);
+    }
+    orders_repository::delete_bucket_items_updated_before(db_conn, before).await
+}
+
+// Copies items of finished order into its user's bucket.
+// Items which cannot be ordered again are skipped,
+// amount of bucket line is limited by max_amount
+pub async fn reorder_into_bucket(
+    db_conn: &mut AsyncPgConnection,
+    order: &OrderInfo,
+    max_amount: i16,
+) -> FieldResult<ReorderReport> {
+    if order.status != OrderStatus::Finished {
+        return Err("Only finished orders can be reordered".into());
+    }
+    let items = orders_repository::select_order_items_by_uuid(db_conn, order.uuid).await?;
+    let product_uuids = items.iter().map(|item| item.product_uuid).collect();
+    let products = orders_repository::select_products_by_uuids(db_conn, product_uuids).await?;
+
+    // several lines of one product with different options share its stock
+    let mut requested: HashMap<Uuid, i32> = HashMap::new();
+    for item in &items {
+        *requested.entry(item.product_uuid).or_default() += i32::from(item.amount);
+    }
+
+    let mut report = ReorderReport {
+        added: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for item in items {
+        let product = products
+            .iter()
+            .find(|product| product.uuid == item.product_uuid)
+            .filter(|product| product.archived_at.is_none());
+        let reason = match product {
+            None => Some(ReorderSkipReason::ProductArchived),
+            Some(product)
+                if !product.is_available
+                    || product
+                        .stock
+                        .is_some_and(|stock| stock < requested[&product.uuid]) =>
+            {
+                Some(ReorderSkipReason::OutOfStock)
+            }
+            Some(product) => select_valid_options(db_conn, product.uuid, &item.option_uuids)
+                .await
+                .err()
+                .map(|_| ReorderSkipReason::OptionsUnavailable),
+        };
+        if let Some(reason) = reason {
+            report.skipped.push(SkippedOrderItem { item, reason });
+            continue;
+        }
+
+        let mut option_uuids = item.option_uuids;
+        option_uuids.sort();
+        let bucket_item = BucketItem {
+            user_uuid: order.user_uuid,
+            product_uuid: item.product_uuid,
+            amount: item.amount.min(max_amount),
+            option_uuids,
+        };
+        let mut bucket_item = orders_repository::upsert_bucket_item(db_conn, bucket_item).await?;
+        if bucket_item.amount > max_amount {
+            bucket_item = orders_repository::update_bucket_item_amount(
+                db_conn,
+                bucket_item.user_uuid,
+                bucket_item.product_uuid,
+                bucket_item.option_uuids,
+                max_amount,
+            )
+            .await?
+            .ok_or("Item not found in bucket")?;
+        }
+        report.added.push(bucket_item);
+    }
+    Ok(report)
+}
+
+pub async fn find_checkout_by_idempotency_key(
+    db_conn: &mut AsyncPgConnection,
+    user_uuid: Uuid,
+    idempotency_key: &str,
+) -> FieldResult<Option<Checkout>> {
+    let now = Utc::now().naive_utc();
+    let checkout = orders_repository::select_checkout_by_idempotency_key(
+        db_conn,
+        user_uuid,
+        idempotency_key,
+        now,
+    )
+    .await?;
+    Ok(checkout)
+}
+
+// Binds idempotency key to created checkout.
+// Should be called in the same transaction as checkout creation
+pub async fn save_idempotency_key(
+    db_conn: &mut AsyncPgConnection,
+    user_uuid: Uuid,
+    idempotency_key: String,
+    checkout_uuid: Uuid,
+    ttl: i32,
+) -> FieldResult<()> {
+    let now = Utc::now().naive_utc();
+    orders_repository::delete_expired_idempotency_key(db_conn, user_uuid, &idempotency_key, now)
+        .await?;
+    let key = CreateIdempotencyKey {
+        user_uuid,
+        idempotency_key,
+        checkout_uuid,
+        expires_at: now + Duration::seconds(ttl as i64),
+    };
+    orders_repository::create_idempotency_key(db_conn, key).await?;
+    Ok(())
 }
 
 pub async fn check_time_expiration(context: &Context<'_>, order: &OrderInfo) -> FieldResult<()> {
@@ -128,8 +1290,8 @@ pub async fn check_time_expiration(context: &Context<'_>, order: &OrderInfo) ->
         .expect("Cannot parse AppState from context")
         .delivery_estimation_time as i64;
     let naive_date_time = Utc::now().naive_utc();
-    match order.status.as_str() {
-        "FINISHED" => {
+    match order.status {
+        OrderStatus::Finished => {
             let difference = (naive_date_time - order.updated_at).num_seconds();
             if delivery_estimation_time - difference > 0 {
                 Ok(())
@@ -141,44 +1303,823 @@ pub async fn check_time_expiration(context: &Context<'_>, order: &OrderInfo) ->
     }
 }
 
+// Order state machine.
+// Every status change must be validated against this table.
+// Assigned order can be finished directly, so couriers which don't report
+// pickup and delivery stages can still complete delivery
+const ORDER_STATUS_TRANSITIONS: &[(OrderStatus, &[OrderStatus])] = &[
+    (
+        OrderStatus::WaitingForCourier,
+        &[
+            OrderStatus::Assigned,
+            OrderStatus::Canceled,
+            OrderStatus::Expired,
+        ],
+    ),
+    (
+        OrderStatus::Assigned,
+        &[
+            OrderStatus::PickedUp,
+            OrderStatus::Finished,
+            OrderStatus::Canceled,
+        ],
+    ),
+    (
+        OrderStatus::PickedUp,
+        &[OrderStatus::InProgress, OrderStatus::Canceled],
+    ),
+    (
+        OrderStatus::InProgress,
+        &[OrderStatus::Finished, OrderStatus::Canceled],
+    ),
+    (OrderStatus::Finished, &[]),
+    (OrderStatus::Canceled, &[]),
+    (OrderStatus::Expired, &[]),
+];
+
+pub fn validate_status_transition(
+    from: OrderStatus,
+    to: OrderStatus,
+) -> Result<(), OrderStatusError> {
+    let allowed = ORDER_STATUS_TRANSITIONS
+        .iter()
+        .find(|(status, _)| *status == from)
+        .is_some_and(|(_, next)| next.contains(&to));
+    if allowed {
+        Ok(())
+    } else {
+        Err(OrderStatusError::IllegalTransition { from, to })
+    }
+}
+
+// Validates transition and changes order status,
+// every change is recorded into order status history
+pub async fn change_order_status(
+    db_conn: &mut AsyncPgConnection,
+    order: &OrderInfo,
+    new_status: OrderStatus,
+    changed_by: Option<Uuid>,
+) -> FieldResult<()> {
+    validate_status_transition(order.status, new_status).map_err(|e| e.extend())?;
+    let order_uuid = order.uuid;
+    let current_status = order.status;
+    let event = CreateOrderStatusEvent {
+        order_uuid,
+        changed_by,
+        from_status: Some(current_status),
+        to_status: new_status,
+        note: None,
+    };
+    db_conn
+        .transaction::<_, Error, _>(|conn| {
+            async move {
+                let updated = orders_repository::update_order_status(
+                    conn,
+                    order_uuid,
+                    current_status,
+                    new_status,
+                )
+                .await?;
+                if updated == 0 {
+                    return Err(OrderStatusError::Conflict {
+                        expected: current_status,
+                    }
+                    .extend());
+                }
+                orders_repository::create_order_status_event(conn, event).await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+}
+
+pub async fn cancel_order(
+    db_conn: &mut AsyncPgConnection,
+    order: &OrderInfo,
+    canceled_by: Uuid,
+    reason: String,
+) -> FieldResult<()> {
+    validate_status_transition(order.status, OrderStatus::Canceled).map_err(|e| e.extend())?;
+    let order_uuid = order.uuid;
+    let current_status = order.status;
+    let event = CreateOrderStatusEvent {
+        order_uuid,
+        changed_by: Some(canceled_by),
+        from_status: Some(current_status),
+        to_status: OrderStatus::Canceled,
+        note: Some(reason.clone()),
+    };
+    db_conn
+        .transaction::<_, Error, _>(|conn| {
+            async move {
+                let updated = orders_repository::cancel_order(
+                    conn,
+                    order_uuid,
+                    current_status,
+                    Some(canceled_by),
+                    reason,
+                )
+                .await?;
+                if updated == 0 {
+                    return Err(OrderStatusError::Conflict {
+                        expected: current_status,
+                    }
+                    .extend());
+                }
+                orders_repository::create_order_status_event(conn, event).await?;
+                // stock is reserved only when order gets a courier
+                if current_status == OrderStatus::WaitingForCourier {
+                    return Ok(());
+                }
+                let items = orders_repository::select_order_items_by_uuid(conn, order_uuid).await?;
+                for item in items {
+                    orders_repository::release_product_stock(
+                        conn,
+                        item.product_uuid,
+                        item.amount.into(),
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+}
+
+// Users can cancel their orders only during the configured time after creation.
+// Admins are not restricted by this window.
+pub fn check_cancellation_time(context: &Context<'_>, order: &OrderInfo) -> FieldResult<()> {
+    let order_cancellation_time = context
+        .data::<Config>()
+        .expect("Cannot parse AppState from context")
+        .order_cancellation_time as i64;
+    let naive_date_time = Utc::now().naive_utc();
+    let difference = (naive_date_time - order.created_at).num_seconds();
+    if order_cancellation_time - difference > 0 {
+        Ok(())
+    } else {
+        Err("Order cannot be canceled anymore".into())
+    }
+}
+
 pub struct OrdersService {
-    // pub db_pool: DbPool,
+    pub db_pool: DbPool,
     // pub create_order_crone: i32,
     // pub jwt_secret: String,
+    pub token_claims_cache: Arc<TokenClaimsCache>,
+    pub users_channel: Channel,
+}
+
+impl OrdersService {
+    // Courier found for user without order to deliver is returned to users service
+    async fn release_courier(&self, courier_uuid: Uuid, user_uuid: Uuid) {
+        if let Err(error) =
+            release_courier(self.users_channel.clone(), courier_uuid, user_uuid).await
+        {
+            tracing::error!(
+                courier_uuid = %courier_uuid,
+                user_uuid = %user_uuid,
+                error.message = ?error.message,
+                "Cannot release courier without order"
+            );
+        }
+    }
+
+    // Courier is released when user has no order for it or assignment failed
+    async fn assign_courier(
+        &self,
+        db_conn: &mut AsyncPgConnection,
+        user_uuid: Uuid,
+        courier_uuid: Uuid,
+    ) -> FieldResult<Option<OrderInfo>> {
+        let result = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move { assign_courier_to_waiting_order(conn, user_uuid, courier_uuid).await }
+                    .scope_boxed()
+            })
+            .await;
+        match result {
+            Ok(Some(order)) => {
+                tracing::info!(
+                    order_uuid = %order.uuid,
+                    courier_uuid = %courier_uuid,
+                    user_uuid = %user_uuid,
+                    "Courier assigned to waiting order"
+                );
+                SimpleBroker::publish(CourierStatus {
+                    mutation_type: MutationType::Completed,
+                    user_uuid,
+                });
+                Ok(Some(order))
+            }
+            Ok(None) => {
+                self.release_courier(courier_uuid, user_uuid).await;
+                Ok(None)
+            }
+            Err(error) => {
+                tracing::error!(
+                    courier_uuid = %courier_uuid,
+                    user_uuid = %user_uuid,
+                    error.message = ?error.message,
+                    "Cannot assign courier to waiting order"
+                );
+                self.release_courier(courier_uuid, user_uuid).await;
+                Err(error)
+            }
+        }
+    }
+
+    // Every order of multi-restaurant checkout needs its own courier.
+    // Returns None if user has no waiting orders anymore
+    // or was added in queue again, users service notifies about next courier then
+    async fn find_courier_for_waiting_order(
+        &self,
+        db_conn: &mut AsyncPgConnection,
+        user_uuid: Uuid,
+    ) -> Option<Uuid> {
+        match orders_repository::lock_orders_waiting_for_courier(db_conn, user_uuid).await {
+            Ok(waiting) if waiting.is_empty() => return None,
+            Ok(_) => {}
+            Err(error) => {
+                tracing::error!(
+                    user_uuid = %user_uuid,
+                    error.message = %error,
+                    "Cannot select orders waiting for courier"
+                );
+                return None;
+            }
+        }
+        match find_free_courier(self.users_channel.clone(), user_uuid).await {
+            Ok(courier_uuid) => courier_uuid,
+            Err(error) => {
+                tracing::error!(
+                    user_uuid = %user_uuid,
+                    error.message = ?error.message,
+                    "Cannot find courier for waiting order"
+                );
+                None
+            }
+        }
+    }
 }
 
 #[tonic::async_trait]
 impl Orders for OrdersService {
+    // Found courier gets the oldest waiting order of user,
+    // couriers for other waiting orders are requested right after that
     async fn notify_founded_courier(
         &self,
         request: Request<CourierForUserRequest>,
     ) -> Result<Response<CourierForUserResponse>, Status> {
         let request = request.into_inner();
-        let courier_uuid = request.courier_uuid;
-        let _courier_rating = request.courier_rating;
-        let user_uuid = Uuid::parse_str(&request.user_uuid).expect("Cannot parse string");
-        println!("new courier: {:?} for user {:?}", courier_uuid, user_uuid);
+        let courier_uuid = Uuid::parse_str(&request.courier_uuid)
+            .map_err(|_| Status::invalid_argument("Invalid courier_uuid"))?;
+        let user_uuid = Uuid::parse_str(&request.user_uuid)
+            .map_err(|_| Status::invalid_argument("Invalid user_uuid"))?;
+        let mut db_conn = self.db_pool.get().await.map_err(|error| {
+            tracing::error!(error.message = %error, "Cannot get database connection");
+            Status::unavailable("Database is unavailable")
+        })?;
 
-        SimpleBroker::publish(CourierStatus {
-            mutation_type: MutationType::Completed,
-            user_uuid,
-        });
-
-        Err(Status::new(Code::Internal, "1".to_string()))
+        let order_created = self
+            .assign_courier(&mut db_conn, user_uuid, courier_uuid)
+            .await
+            .map_err(|error| Status::internal(error.message))?
+            .is_some();
+        if order_created {
+            let mut next_courier = self
+                .find_courier_for_waiting_order(&mut db_conn, user_uuid)
+                .await;
+            while let Some(courier_uuid) = next_courier {
+                next_courier = match self
+                    .assign_courier(&mut db_conn, user_uuid, courier_uuid)
+                    .await
+                {
+                    Ok(Some(_)) => {
+                        self.find_courier_for_waiting_order(&mut db_conn, user_uuid)
+                            .await
+                    }
+                    // courier is already released, order stays waiting until it expires
+                    _ => None,
+                };
+            }
+        }
+        Ok(Response::new(CourierForUserResponse { order_created }))
     }
 
     async fn notify_expiration_time(
         &self,
         request: Request<TimeExpirationRequest>,
     ) -> Result<Response<TimeExpirationResponse>, Status> {
-        let user_uuid = &request.into_inner().user_uuid;
-        let user_uuid = Uuid::parse_str(user_uuid).expect("Cannot parse string");
-        println!("expiration time: {:?}", user_uuid);
+        let user_uuid = Uuid::parse_str(&request.into_inner().user_uuid)
+            .map_err(|_| Status::invalid_argument("Invalid user_uuid"))?;
+        let mut db_conn = self.db_pool.get().await.map_err(|error| {
+            tracing::error!(error.message = %error, "Cannot get database connection");
+            Status::unavailable("Database is unavailable")
+        })?;
+
+        let orders = db_conn
+            .transaction::<_, Error, _>(|conn| {
+                async move { expire_waiting_orders(conn, user_uuid).await }.scope_boxed()
+            })
+            .await
+            .map_err(|error| {
+                tracing::error!(
+                    user_uuid = %user_uuid,
+                    error.message = ?error.message,
+                    "Cannot expire waiting orders"
+                );
+                Status::internal(error.message)
+            })?;
+        for order in &orders {
+            tracing::info!(
+                order_uuid = %order.uuid,
+                user_uuid = %user_uuid,
+                "Order expired without courier"
+            );
+        }
+
         SimpleBroker::publish(CourierStatus {
             mutation_type: MutationType::Expired,
             user_uuid,
         });
+        Ok(Response::new(TimeExpirationResponse {
+            user_notified: true,
+        }))
+    }
+
+    // Users service revokes token on logout or role change,
+    // cached claims of the token must not be used anymore
+    // and the token is rejected in local auth mode as well
+    async fn revoke_token(
+        &self,
+        request: Request<RevokeTokenRequest>,
+    ) -> Result<Response<RevokeTokenResponse>, Status> {
+        let token = request.into_inner().token;
+        if token.is_empty() {
+            return Err(Status::invalid_argument("Token is required"));
+        }
+        let token_revoked = self.token_claims_cache.revoke(&token);
+        Ok(Response::new(RevokeTokenResponse { token_revoked }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_STATUSES: [OrderStatus; 7] = [
+        OrderStatus::WaitingForCourier,
+        OrderStatus::Assigned,
+        OrderStatus::PickedUp,
+        OrderStatus::InProgress,
+        OrderStatus::Finished,
+        OrderStatus::Canceled,
+        OrderStatus::Expired,
+    ];
+
+    #[test]
+    fn every_status_has_transitions() {
+        for status in ALL_STATUSES {
+            assert!(
+                ORDER_STATUS_TRANSITIONS
+                    .iter()
+                    .any(|(from, _)| *from == status),
+                "{} is missing in transition table",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn delivery_flow_is_allowed() {
+        let flow = [
+            OrderStatus::WaitingForCourier,
+            OrderStatus::Assigned,
+            OrderStatus::PickedUp,
+            OrderStatus::InProgress,
+            OrderStatus::Finished,
+        ];
+        for pair in flow.windows(2) {
+            assert!(validate_status_transition(pair[0], pair[1]).is_ok());
+        }
+    }
+
+    #[test]
+    fn assigned_order_can_be_finished() {
+        assert!(validate_status_transition(OrderStatus::Assigned, OrderStatus::Finished).is_ok());
+    }
+
+    #[test]
+    fn final_statuses_cannot_change() {
+        for from in [
+            OrderStatus::Finished,
+            OrderStatus::Canceled,
+            OrderStatus::Expired,
+        ] {
+            for to in ALL_STATUSES {
+                assert!(matches!(
+                    validate_status_transition(from, to),
+                    Err(OrderStatusError::IllegalTransition { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn status_cannot_go_back() {
+        assert!(
+            validate_status_transition(OrderStatus::InProgress, OrderStatus::Assigned).is_err()
+        );
+        assert!(validate_status_transition(OrderStatus::PickedUp, OrderStatus::Assigned).is_err());
+        assert!(validate_status_transition(OrderStatus::Assigned, OrderStatus::Assigned).is_err());
+    }
+
+    #[test]
+    fn only_waiting_order_expires() {
+        for from in ALL_STATUSES {
+            let result = validate_status_transition(from, OrderStatus::Expired);
+            assert_eq!(result.is_ok(), from == OrderStatus::WaitingForCourier);
+        }
+    }
+
+    fn restaurant(time_zone: &str) -> Restaurant {
+        let created_at = NaiveDate::from_ymd_opt(2026, 1, 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("valid date");
+        Restaurant {
+            uuid: Uuid::from_u128(1),
+            name: "Restaurant".to_string(),
+            address: None,
+            phone: None,
+            email: None,
+            created_at,
+            updated_at: created_at,
+            account_uuid: None,
+            time_zone: time_zone.to_string(),
+        }
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).expect("valid time")
+    }
+
+    fn utc(date: &str) -> DateTime<Utc> {
+        date.parse().expect("valid date time")
+    }
+
+    fn weekly(weekday: i16, opens_at: NaiveTime, closes_at: NaiveTime) -> OpeningHours {
+        OpeningHours {
+            weekday,
+            opens_at,
+            closes_at,
+        }
+    }
+
+    fn holiday(date: &str, hours: Option<(NaiveTime, NaiveTime)>) -> Holiday {
+        Holiday {
+            restaurant_uuid: Uuid::from_u128(1),
+            date: date.parse().expect("valid date"),
+            opens_at: hours.map(|(opens_at, _)| opens_at),
+            closes_at: hours.map(|(_, closes_at)| closes_at),
+            note: None,
+        }
+    }
+
+    #[test]
+    fn restaurant_without_hours_is_always_open() {
+        let restaurant = restaurant("UTC");
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &[],
+            &[],
+            utc("2026-03-02T03:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn weekly_hours_are_checked_in_local_time() {
+        // 2026-03-02 is Monday
+        let restaurant = restaurant("America/New_York");
+        let hours = [weekly(1, time(9, 0), time(17, 0))];
+        // 08:59 and 17:00 in New York during winter time
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-03-02T13:59:00Z")
+        ));
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-03-02T14:00:00Z")
+        ));
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-03-02T22:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn daylight_saving_time_shifts_opening_hours() {
+        let restaurant = restaurant("Europe/Berlin");
+        // 2026-03-27 and 2026-04-03 are Fridays, DST starts on 2026-03-29
+        let hours = [weekly(5, time(10, 0), time(22, 0))];
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-03-27T09:00:00Z")
+        ));
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-04-03T07:30:00Z")
+        ));
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-04-03T08:00:00Z")
+        ));
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-04-03T20:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn weekday_follows_local_date() {
+        // Monday 01:00 in Tokyo is still Sunday in UTC
+        let restaurant = restaurant("Asia/Tokyo");
+        let hours = [weekly(1, time(0, 0), time(2, 0))];
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &[],
+            utc("2026-03-01T16:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn holiday_overrides_weekly_hours() {
+        let restaurant = restaurant("UTC");
+        let hours = [weekly(1, time(9, 0), time(17, 0))];
+        let closed = [holiday("2026-03-02", None)];
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &closed,
+            utc("2026-03-02T12:00:00Z")
+        ));
+
+        let short_day = [holiday("2026-03-02", Some((time(10, 0), time(12, 0))))];
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &short_day,
+            utc("2026-03-02T11:00:00Z")
+        ));
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &short_day,
+            utc("2026-03-02T13:00:00Z")
+        ));
+        // holiday of another date doesn't matter
+        let other_day = [holiday("2026-03-03", None)];
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &hours,
+            &other_day,
+            utc("2026-03-02T12:00:00Z")
+        ));
+    }
+
+    #[test]
+    fn holiday_applies_to_local_date() {
+        // 23:30 UTC on 2026-03-01 is already 2026-03-02 in Berlin
+        let restaurant = restaurant("Europe/Berlin");
+        let closed = [holiday("2026-03-02", None)];
+        assert!(!is_restaurant_open_at(
+            &restaurant,
+            &[],
+            &closed,
+            utc("2026-03-01T23:30:00Z")
+        ));
+        assert!(is_restaurant_open_at(
+            &restaurant,
+            &[],
+            &closed,
+            utc("2026-03-01T22:30:00Z")
+        ));
+    }
+
+    #[test]
+    fn restaurant_schedule_is_validated() {
+        assert!(validate_restaurant_schedule(Some("Europe/Berlin"), None, None).is_ok());
+        assert!(validate_restaurant_schedule(Some("Mars/Olympus"), None, None).is_err());
+        let hours = [OpeningHoursInput {
+            weekday: 8,
+            opens_at: time(9, 0),
+            closes_at: time(17, 0),
+        }];
+        assert!(validate_restaurant_schedule(None, Some(&hours), None).is_err());
+        let hours = [OpeningHoursInput {
+            weekday: 1,
+            opens_at: time(17, 0),
+            closes_at: time(9, 0),
+        }];
+        assert!(validate_restaurant_schedule(None, Some(&hours), None).is_err());
+        let holidays = [HolidayInput {
+            date: "2026-03-02".parse().expect("valid date"),
+            opens_at: Some(time(9, 0)),
+            closes_at: None,
+            note: None,
+        }];
+        assert!(validate_restaurant_schedule(None, None, Some(&holidays)).is_err());
+    }
+
+    fn category(uuid: u128, parent: Option<u128>, position: i32) -> Category {
+        let created_at = NaiveDate::from_ymd_opt(2026, 1, 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("valid date");
+        Category {
+            uuid: Uuid::from_u128(uuid),
+            parent_uuid: parent.map(Uuid::from_u128),
+            name: format!("category-{}", uuid),
+            display_name: format!("Category {}", uuid),
+            position,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    // 1 -> 2 -> 3 -> 4 -> 5 -> 6, 1 -> 7, 8
+    fn categories() -> Vec<Category> {
+        vec![
+            category(1, None, 0),
+            category(8, None, 1),
+            category(2, Some(1), 0),
+            category(7, Some(1), 1),
+            category(3, Some(2), 0),
+            category(4, Some(3), 0),
+            category(5, Some(4), 0),
+            category(6, Some(5), 0),
+        ]
+    }
+
+    fn uuids(values: &[u128]) -> Vec<Uuid> {
+        values.iter().map(|value| Uuid::from_u128(*value)).collect()
+    }
+
+    #[test]
+    fn descendants_include_every_level() {
+        let mut found = category_with_descendants(&categories(), Uuid::from_u128(1));
+        found.sort();
+        assert_eq!(found, uuids(&[1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(
+            category_with_descendants(&categories(), Uuid::from_u128(6)),
+            uuids(&[6])
+        );
+    }
+
+    #[test]
+    fn descendants_stop_on_cycle() {
+        let cyclic = vec![category(1, Some(2), 0), category(2, Some(1), 0)];
+        let mut found = category_with_descendants(&cyclic, Uuid::from_u128(1));
+        found.sort();
+        assert_eq!(found, uuids(&[1, 2]));
+    }
+
+    #[test]
+    fn category_tree_keeps_position_order() {
+        let tree = build_category_tree(&categories(), None);
+        let roots: Vec<Uuid> = tree.iter().map(|node| node.category.uuid).collect();
+        assert_eq!(roots, uuids(&[1, 8]));
+        let children: Vec<Uuid> = tree[0]
+            .children
+            .iter()
+            .map(|node| node.category.uuid)
+            .collect();
+        assert_eq!(children, uuids(&[2, 7]));
+    }
+
+    #[test]
+    fn flat_categories_follow_tree_order_with_depth() {
+        let flat = flatten_category_tree(&categories());
+        let order: Vec<(Uuid, i32)> = flat
+            .iter()
+            .map(|flat| (flat.category.uuid, flat.depth))
+            .collect();
+        let expected: Vec<(Uuid, i32)> = [
+            (1, 0),
+            (2, 1),
+            (3, 2),
+            (4, 3),
+            (5, 4),
+            (6, 5),
+            (7, 1),
+            (8, 0),
+        ]
+        .iter()
+        .map(|(uuid, depth)| (Uuid::from_u128(*uuid), *depth))
+        .collect();
+        assert_eq!(order, expected);
+    }
+
+    fn created_at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 1, 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("valid date")
+    }
+
+    fn group(uuid: u128, min_select: i32, max_select: i32) -> ModifierGroup {
+        ModifierGroup {
+            uuid: Uuid::from_u128(uuid),
+            product_uuid: Uuid::from_u128(100),
+            name: format!("group-{}", uuid),
+            min_select,
+            max_select,
+            position: 0,
+            created_at: created_at(),
+        }
+    }
+
+    fn option(uuid: u128, group_uuid: u128, position: i32) -> ModifierOption {
+        ModifierOption {
+            uuid: Uuid::from_u128(uuid),
+            group_uuid: Uuid::from_u128(group_uuid),
+            name: format!("option-{}", uuid),
+            price_delta: Money(50),
+            position,
+            created_at: created_at(),
+        }
+    }
+
+    // size: exactly one of 11, 12; toppings: up to two of 21, 22, 23
+    fn modifiers() -> (Vec<ModifierGroup>, Vec<ModifierOption>) {
+        (
+            vec![group(1, 1, 1), group(2, 0, 2)],
+            vec![
+                option(11, 1, 0),
+                option(12, 1, 1),
+                option(21, 2, 0),
+                option(22, 2, 1),
+                option(23, 2, 2),
+            ],
+        )
+    }
+
+    fn selected_uuids(result: FieldResult<Vec<ModifierOption>>) -> Vec<Uuid> {
+        result
+            .expect("options are valid")
+            .iter()
+            .map(|option| option.uuid)
+            .collect()
+    }
+
+    #[test]
+    fn valid_options_are_returned_in_display_order() {
+        let (groups, options) = modifiers();
+        let selected = uuids(&[22, 12, 21]);
+        assert_eq!(
+            selected_uuids(validate_options(&groups, options, &selected)),
+            uuids(&[12, 21, 22])
+        );
+    }
+
+    #[test]
+    fn product_without_modifiers_accepts_no_options() {
+        assert!(validate_options(&[], Vec::new(), &[]).is_ok());
+        assert!(validate_options(&[], Vec::new(), &uuids(&[11])).is_err());
+    }
+
+    #[test]
+    fn required_group_must_be_selected() {
+        let (groups, options) = modifiers();
+        assert!(validate_options(&groups, options, &uuids(&[21])).is_err());
+    }
+
+    #[test]
+    fn group_maximum_is_enforced() {
+        let (groups, options) = modifiers();
+        assert!(validate_options(&groups, options.clone(), &uuids(&[11, 12])).is_err());
+        assert!(validate_options(&groups, options, &uuids(&[11, 21, 22, 23])).is_err());
+    }
 
-        Err(Status::new(Code::Internal, "1".to_string()))
+    #[test]
+    fn unknown_and_repeated_options_are_rejected() {
+        let (groups, options) = modifiers();
+        assert!(validate_options(&groups, options.clone(), &uuids(&[11, 99])).is_err());
+        assert!(validate_options(&groups, options, &uuids(&[11, 21, 21])).is_err());
     }
 }
@@ -1,16 +1,17 @@
 use crate::{
-    models::orders_model::OrderQueueInfo,
+    models::orders_model::{OrderInfo, OrderQueueInfo},
     repository::orders_repository::get_courier_rating,
-    resources::postgresql::DbConn,
     utils::{
         configs::Config,
+        errors::UsersServiceError,
         grpc::users_grpc::{
-            users_client::UsersClient, FindCourierRequest, TokenClaimsRequest,
-            UpdateCourierRatingRequest, WaitForCourierRequest,
+            users_client::UsersClient, FindCourierRequest, OrderCanceledRequest,
+            ReleaseCourierRequest, TokenClaimsRequest, UpdateCourierRatingRequest,
+            WaitForCourierRequest,
         },
     },
 };
-use async_graphql::{Context, FieldResult};
+use async_graphql::{Context, ErrorExtensions, FieldResult};
 use axum::{
     async_trait,
     extract::FromRequestParts,
@@ -18,25 +19,35 @@ use axum::{
     http::{self, request::Parts},
     Extension, RequestPartsExt,
 };
+use diesel_async::AsyncPgConnection;
 use hyper::StatusCode;
-use tonic::{Code, Status};
+use tonic::{transport::Channel, Code, Status};
 use uuid::Uuid;
 
+// Client over shared channel, connection is established on first request
+// and reused by all following requests
+pub fn users_client(context: &Context<'_>) -> UsersClient<Channel> {
+    let config = context
+        .data::<Config>()
+        .expect("Cannot parse AppState from context");
+    UsersClient::new(config.users_channel.clone())
+}
+
 pub async fn get_token_claims(
     token: String,
-    grpc_users_address: String,
+    users_channel: Channel,
 ) -> Result<TokenClaims, Status> {
-    let mut client = UsersClient::connect(grpc_users_address)
-        .await
-        .expect("Cannot connect to user service");
+    let mut client = UsersClient::new(users_channel);
     let request = tonic::Request::new(TokenClaimsRequest { token });
 
     let response = client.send_token_claims(request).await;
     match response {
         Ok(response) => {
             let token_claims = response.into_inner();
+            let uuid = Uuid::parse_str(&token_claims.uuid)
+                .map_err(|_| Status::internal("Users service returned invalid user uuid"))?;
             Ok(TokenClaims {
-                uuid: Uuid::parse_str(&token_claims.uuid).expect("Cannot parse uuid from string"),
+                uuid,
                 role: token_claims.role,
             })
         }
@@ -44,7 +55,7 @@ pub async fn get_token_claims(
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct TokenClaims {
     pub uuid: Uuid,
     pub role: String,
@@ -70,57 +81,104 @@ where
             .await
             .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
 
-        let token_claims = get_token_claims(token, config.grpc_users_address).await;
+        if let Some(verifier) = &config.jwt_verifier {
+            // signature of revoked token stays valid until it expires
+            if config.token_claims_cache.is_revoked(&token) {
+                tracing::debug!("Revoked token rejected");
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+            return verifier.verify(&token).map_err(|error| {
+                tracing::debug!(error.message = %error, "Token verification failed");
+                StatusCode::UNAUTHORIZED
+            });
+        }
+        // claims are cached for a short time,
+        // so users service is not asked on every request
+        if let Some(claims) = config.token_claims_cache.get(&token) {
+            return Ok(claims);
+        }
+        let token_claims = get_token_claims(token.clone(), config.users_channel).await;
         match token_claims {
-            Ok(claims) => Ok(claims),
+            Ok(claims) => {
+                config.token_claims_cache.insert(&token, claims.clone());
+                Ok(claims)
+            }
             Err(status) => match status.code() {
                 Code::Unauthenticated => Err(StatusCode::UNAUTHORIZED),
-                _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                Code::Unavailable | Code::DeadlineExceeded => Err(StatusCode::SERVICE_UNAVAILABLE),
+                _ => {
+                    tracing::error!(
+                        code = ?status.code(),
+                        message = status.message(),
+                        "Cannot get token claims from users service"
+                    );
+                    Err(StatusCode::INTERNAL_SERVER_ERROR)
+                }
             },
         }
     }
 }
 
-pub async fn find_free_courier(context: &Context<'_>, user_uuid: Uuid) -> FieldResult<Uuid> {
-    let config = &context
-        .data::<Config>()
-        .expect("Cannot parse AppState from context");
-    let mut client = UsersClient::connect(config.grpc_users_address.clone())
-        .await
-        .expect("Cannot connect to user service");
+// Returns None when there are no free couriers and user was added in queue,
+// users service calls NotifyFoundedCourier when a courier becomes free
+pub async fn find_free_courier(
+    users_channel: Channel,
+    user_uuid: Uuid,
+) -> FieldResult<Option<Uuid>> {
+    let mut client = UsersClient::new(users_channel);
 
     let request = tonic::Request::new(FindCourierRequest {
         user_uuid: user_uuid.to_string(),
     });
-    println!("--find_free_courier making request");
+    tracing::debug!(user_uuid = %user_uuid, "Requesting free courier");
     let response = client.find_courier(request).await;
-    println!("--find_free_courier handling response");
     match response {
         Ok(response) => {
             let response = response.into_inner();
             if response.added_to_queue {
-                // In case user was added in queue
-                Err("Added to queue".into())
+                Ok(None)
             } else {
-                Ok(Uuid::parse_str(&response.courier_uuid).expect("Cannot parse uuid"))
+                let courier_uuid = Uuid::parse_str(&response.courier_uuid)
+                    .map_err(|_| "Users service returned invalid courier uuid")?;
+                Ok(Some(courier_uuid))
             }
         }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+        Err(status) => Err(UsersServiceError(status).extend()),
+    }
+}
+
+// Returns courier reserved by find_free_courier back to users service
+pub async fn release_courier(
+    users_channel: Channel,
+    courier_uuid: Uuid,
+    user_uuid: Uuid,
+) -> FieldResult<()> {
+    let mut client = UsersClient::new(users_channel);
+
+    let request = tonic::Request::new(ReleaseCourierRequest {
+        courier_uuid: courier_uuid.to_string(),
+        user_uuid: user_uuid.to_string(),
+    });
+    let result = client.release_courier(request).await;
+    match result {
+        Ok(result) => {
+            if result.into_inner().courier_released {
+                Ok(())
+            } else {
+                Err("Courier was not released".into())
+            }
+        }
+        Err(status) => Err(UsersServiceError(status).extend()),
     }
 }
 
 pub async fn update_courier_rating(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     context: &Context<'_>,
     courier_uuid: Uuid,
 ) -> FieldResult<String> {
     let rating = count_average_rating(db_conn, courier_uuid).await?;
-    let config = &context
-        .data::<Config>()
-        .expect("Cannot parse AppState from context");
-    let mut client = UsersClient::connect(config.grpc_users_address.clone())
-        .await
-        .expect("Cannot connect to user service");
+    let mut client = users_client(context);
 
     let request = tonic::Request::new(UpdateCourierRatingRequest {
         courier_uuid: courier_uuid.to_string(),
@@ -129,16 +187,17 @@ pub async fn update_courier_rating(
     let result = client.update_courier_rating(request).await;
     match result {
         Ok(result) => Ok(result.into_inner().message),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+        Err(status) => Err(UsersServiceError(status).extend()),
     }
 }
 
 pub async fn count_average_rating(
-    db_conn: &mut DbConn<'_>,
+    db_conn: &mut AsyncPgConnection,
     courier_uuid: Uuid,
 ) -> FieldResult<f32> {
     let courier_rating = get_courier_rating(db_conn, courier_uuid).await?;
-    let mut rating: Vec<i16> = courier_rating.into_iter().map(|x| x.unwrap()).collect();
+    // orders which were not rated yet are skipped
+    let mut rating: Vec<i16> = courier_rating.into_iter().flatten().collect();
     let mut length = rating.len();
     match length {
         0..=99 => {
@@ -169,12 +228,7 @@ pub async fn check_courier_from_queue(
     context: &Context<'_>,
     order_uuid: Uuid,
 ) -> FieldResult<OrderQueueInfo> {
-    let config = &context
-        .data::<Config>()
-        .expect("Cannot parse AppState from context");
-    let mut client = UsersClient::connect(config.grpc_users_address.clone())
-        .await
-        .expect("Cannot connect to user service");
+    let mut client = users_client(context);
 
     let request = tonic::Request::new(WaitForCourierRequest {
         order_uuid: order_uuid.to_string(),
@@ -189,6 +243,32 @@ pub async fn check_courier_from_queue(
             })
         }
         // add Access Error
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+        Err(status) => Err(UsersServiceError(status).extend()),
+    }
+}
+
+pub async fn notify_courier_about_cancellation(
+    context: &Context<'_>,
+    order: &OrderInfo,
+    reason: String,
+) -> FieldResult<()> {
+    let courier_uuid = order.courier_uuid.ok_or("Order has no courier")?;
+    let mut client = users_client(context);
+
+    let request = tonic::Request::new(OrderCanceledRequest {
+        order_uuid: order.uuid.to_string(),
+        courier_uuid: courier_uuid.to_string(),
+        reason,
+    });
+    let result = client.notify_order_canceled(request).await;
+    match result {
+        Ok(result) => {
+            if result.into_inner().courier_notified {
+                Ok(())
+            } else {
+                Err("Courier was not notified".into())
+            }
+        }
+        Err(status) => Err(UsersServiceError(status).extend()),
     }
 }
@@ -1,15 +1,20 @@
+use super::jwt_verifier::{AuthMode, JwtVerifier};
+use super::token_claims_cache::TokenClaimsCache;
 use super::{grpc::orders_grpc::orders_server::OrdersServer, permission_policy::Policy};
 use crate::{
     middleware::tracing_middleware::init_subscriber,
+    models::orders_model::Money,
     resources::postgresql::{establish_connection_pool, DbPool},
     routes::api::config::api_v1_graphql_config,
-    services::orders_service::OrdersService,
+    services::orders_service::{self, OrdersService},
 };
 use axum::{routing::IntoMakeService, Server};
 use dotenvy::dotenv;
 use hyper::server::conn::AddrIncoming;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
-use tonic::transport::server::Router;
+use tonic::transport::{server::Router, Channel, Endpoint};
 use tracing::info;
 
 #[derive(Debug, StructOpt, Clone)]
@@ -37,10 +42,143 @@ pub struct Opt {
     )]
     pub grpc_analytics_address: String,
 
+    // "grpc" asks users service for token claims,
+    // "local" verifies JWT signature with JWT_SECRET or keys from JWKS_PATH
+    #[structopt(long, env = "AUTH_MODE", default_value = "grpc")]
+    pub auth_mode: AuthMode,
+
+    // Shared secret of HS256 tokens, used in local auth mode
+    #[structopt(long, env = "JWT_SECRET", hide_env_values = true)]
+    pub jwt_secret: Option<String>,
+
+    // JWKS file with RS256/ES256 public keys, used in local auth mode
+    #[structopt(long, env = "JWKS_PATH")]
+    pub jwks_path: Option<String>,
+
+    // Expected "aud" claim of tokens, not checked when empty
+    #[structopt(long, env = "JWT_AUDIENCE")]
+    pub jwt_audience: Option<String>,
+
+    // During this time token claims are taken from cache
+    // instead of users service
+    // in seconds
+    #[structopt(long, env = "TOKEN_CLAIMS_CACHE_TTL", default_value = "60")]
+    pub token_claims_cache_ttl: u64,
+
+    // Maximum number of cached tokens, 0 disables cache
+    #[structopt(long, env = "TOKEN_CLAIMS_CACHE_CAPACITY", default_value = "10000")]
+    pub token_claims_cache_capacity: usize,
+
+    // Revoked tokens are remembered during this time,
+    // at least as long as TOKEN_CLAIMS_CACHE_TTL.
+    // In local auth mode it should cover lifetime of tokens,
+    // otherwise revoked token is accepted again until it expires
+    // in seconds
+    #[structopt(long, env = "TOKEN_REVOCATION_TTL", default_value = "3600")]
+    pub token_revocation_ttl: u64,
+
+    // Maximum number of remembered revoked tokens,
+    // the oldest revocation is forgotten when it is reached, 0 disables revocation
+    #[structopt(long, env = "TOKEN_REVOCATION_CAPACITY", default_value = "100000")]
+    pub token_revocation_capacity: usize,
+
+    // Time to establish connection to other gRPC services
+    // in seconds
+    #[structopt(long, env = "GRPC_CONNECT_TIMEOUT", default_value = "5")]
+    pub grpc_connect_timeout: u64,
+
+    // Time to wait for response of other gRPC services
+    // in seconds
+    #[structopt(long, env = "GRPC_REQUEST_TIMEOUT", default_value = "10")]
+    pub grpc_request_timeout: u64,
+
+    // Interval of HTTP2 pings which keep idle connections open
+    // in seconds
+    #[structopt(long, env = "GRPC_KEEPALIVE_INTERVAL", default_value = "30")]
+    pub grpc_keepalive_interval: u64,
+
+    // Maximum number of concurrent requests over one channel
+    #[structopt(long, env = "GRPC_CONCURRENCY_LIMIT", default_value = "256")]
+    pub grpc_concurrency_limit: usize,
+
+    // Number of attempts to report finished order to analytics service, at least 1
+    #[structopt(
+        long,
+        env = "ANALYTICS_RETRY_ATTEMPTS",
+        default_value = "5",
+        parse(try_from_str = parse_retry_attempts)
+    )]
+    pub analytics_retry_attempts: u32,
+
+    // Delay before the first retry of analytics report,
+    // doubled after every attempt up to a minute
+    // in seconds
+    #[structopt(long, env = "ANALYTICS_RETRY_DELAY", default_value = "1")]
+    pub analytics_retry_delay: u64,
+
     // During this time user can estimate delivery after it was finished
     // in seconds
     #[structopt(long, env = "DELIVERY_ESTIMATION_TIME", default_value = "600")]
     pub delivery_estimation_time: i32,
+
+    // During this time user can cancel order after it was created
+    // in seconds
+    #[structopt(long, env = "ORDER_CANCELLATION_TIME", default_value = "300")]
+    pub order_cancellation_time: i32,
+
+    // During this time repeated requests with the same idempotency key
+    // return already created order
+    // in seconds
+    #[structopt(long, env = "IDEMPOTENCY_KEY_TTL", default_value = "86400")]
+    pub idempotency_key_ttl: i32,
+
+    // Fee added to every order, stored on order at creation time
+    // in major units of currency, e.g. "2.50"
+    #[structopt(long, env = "DELIVERY_FEE", default_value = "0")]
+    pub delivery_fee: Money,
+
+    // Subtotal of the whole bucket without delivery fees must be at least this amount
+    // in major units of currency, e.g. "10.00". Checked once per checkout,
+    // orders of separate restaurants in it are not checked on their own
+    #[structopt(long, env = "MIN_ORDER_AMOUNT", default_value = "0")]
+    pub min_order_amount: Money,
+
+    // ISO 4217 code of currency used for all prices
+    #[structopt(
+        long,
+        env = "CURRENCY",
+        default_value = "USD",
+        parse(try_from_str = Money::parse_currency)
+    )]
+    pub currency: String,
+
+    // Maximum amount of one product (with the same options) in bucket
+    #[structopt(long, env = "MAX_BUCKET_ITEM_AMOUNT", default_value = "50")]
+    pub max_bucket_item_amount: i16,
+
+    // Reject buckets with products from several restaurants
+    // instead of splitting them into one order per restaurant
+    #[structopt(
+        long,
+        env = "FORBID_MIXED_RESTAURANTS",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    pub forbid_mixed_restaurants: bool,
+
+    // Bucket lines which were not changed during this time are removed
+    // in seconds
+    #[structopt(long, env = "BUCKET_TTL", default_value = "604800")]
+    pub bucket_ttl: i32,
+
+    // How often abandoned bucket lines are looked for
+    // in seconds
+    #[structopt(long, env = "BUCKET_EXPIRY_INTERVAL", default_value = "3600")]
+    pub bucket_expiry_interval: u64,
+
+    // Only log how many bucket lines would be removed
+    #[structopt(long)]
+    pub bucket_expiry_dry_run: bool,
 }
 
 #[derive(Clone)]
@@ -50,9 +188,29 @@ pub struct Config {
     pub permission_policy: Policy,
     pub bind_address: String,
     pub delivery_estimation_time: i32,
+    pub order_cancellation_time: i32,
+    pub idempotency_key_ttl: i32,
+    pub delivery_fee: Money,
+    pub min_order_amount: Money,
+    pub currency: String,
+    pub max_bucket_item_amount: i16,
+    pub forbid_mixed_restaurants: bool,
+    pub bucket_ttl: i32,
+    pub bucket_expiry_interval: u64,
+    pub bucket_expiry_dry_run: bool,
     pub grpc_users_address: String,
     pub grpc_orders_address: String,
     pub grpc_analytics_address: String,
+    // Shared by all requests to users service
+    pub users_channel: Channel,
+    // Shared by all requests to analytics service
+    pub analytics_channel: Channel,
+    pub analytics_retry_attempts: u32,
+    pub analytics_retry_delay: u64,
+    pub token_claims_cache: Arc<TokenClaimsCache>,
+    pub auth_mode: AuthMode,
+    // Set only in local auth mode
+    pub jwt_verifier: Option<Arc<JwtVerifier>>,
 }
 
 impl Config {
@@ -65,24 +223,97 @@ impl Config {
         let db_pool = establish_connection_pool(opt.database_url.clone()).await;
 
         let permission_policy = Policy::new().await;
+        let users_channel = build_grpc_channel(&opt, &opt.grpc_users_address);
+        let analytics_channel = build_grpc_channel(&opt, &opt.grpc_analytics_address);
+        let auth_mode = opt.auth_mode;
+        let jwt_verifier = match auth_mode {
+            AuthMode::Grpc => None,
+            AuthMode::Local => Some(Arc::new(
+                JwtVerifier::new(
+                    opt.jwt_secret.as_deref(),
+                    opt.jwks_path.as_deref(),
+                    opt.jwt_audience.clone(),
+                )
+                .expect("Cannot build JWT verifier"),
+            )),
+        };
+        info!(%auth_mode, "Authentication mode");
+        let token_claims_cache = Arc::new(TokenClaimsCache::new(
+            Duration::from_secs(opt.token_claims_cache_ttl),
+            opt.token_claims_cache_capacity,
+            Duration::from_secs(opt.token_revocation_ttl),
+            opt.token_revocation_capacity,
+        ));
         let bind_address = opt.bind_address;
         let delivery_estimation_time = opt.delivery_estimation_time;
+        let order_cancellation_time = opt.order_cancellation_time;
+        let idempotency_key_ttl = opt.idempotency_key_ttl;
+        let delivery_fee = opt.delivery_fee;
+        let min_order_amount = opt.min_order_amount;
+        let currency = opt.currency;
+        let max_bucket_item_amount = opt.max_bucket_item_amount;
+        let forbid_mixed_restaurants = opt.forbid_mixed_restaurants;
+        let bucket_ttl = opt.bucket_ttl;
+        let bucket_expiry_interval = opt.bucket_expiry_interval;
+        let bucket_expiry_dry_run = opt.bucket_expiry_dry_run;
         let grpc_users_address = opt.grpc_users_address;
         let grpc_orders_address = opt.grpc_orders_address;
         let grpc_analytics_address = opt.grpc_analytics_address;
+        let analytics_retry_attempts = opt.analytics_retry_attempts;
+        let analytics_retry_delay = opt.analytics_retry_delay;
 
         Config {
             db_pool,
             permission_policy,
             bind_address,
             delivery_estimation_time,
+            order_cancellation_time,
+            idempotency_key_ttl,
+            delivery_fee,
+            min_order_amount,
+            currency,
+            max_bucket_item_amount,
+            forbid_mixed_restaurants,
+            bucket_ttl,
+            bucket_expiry_interval,
+            bucket_expiry_dry_run,
             grpc_users_address,
             grpc_orders_address,
             grpc_analytics_address,
+            users_channel,
+            analytics_channel,
+            analytics_retry_attempts,
+            analytics_retry_delay,
+            token_claims_cache,
+            auth_mode,
+            jwt_verifier,
         }
     }
 }
 
+// Channel connects on first request and reconnects by itself,
+// so other service doesn't have to be up when application starts
+fn build_grpc_channel(opt: &Opt, address: &str) -> Channel {
+    Endpoint::from_shared(address.to_string())
+        .expect("Cannot parse gRPC address")
+        .connect_timeout(Duration::from_secs(opt.grpc_connect_timeout))
+        .timeout(Duration::from_secs(opt.grpc_request_timeout))
+        .http2_keep_alive_interval(Duration::from_secs(opt.grpc_keepalive_interval))
+        .keep_alive_timeout(Duration::from_secs(opt.grpc_request_timeout))
+        .keep_alive_while_idle(true)
+        .concurrency_limit(opt.grpc_concurrency_limit)
+        .connect_lazy()
+}
+
+// Zero attempts would drop reports without any request or log
+fn parse_retry_attempts(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(0) => Err("ANALYTICS_RETRY_ATTEMPTS must be at least 1".to_string()),
+        Ok(attempts) => Ok(attempts),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
 pub struct Application {
     server: axum::Server<AddrIncoming, IntoMakeService<axum::Router>>,
 }
@@ -111,14 +342,71 @@ impl Application {
     }
 }
 
+pub struct BucketExpiryJob {
+    db_pool: DbPool,
+    ttl: i32,
+    interval: Duration,
+    dry_run: bool,
+}
+
+impl BucketExpiryJob {
+    pub async fn build(config: &Config) -> Result<Self, anyhow::Error> {
+        info!("Building bucket expiry job");
+        if config.bucket_ttl <= 0 {
+            anyhow::bail!("BUCKET_TTL must be positive");
+        }
+        if config.bucket_expiry_interval == 0 {
+            anyhow::bail!("BUCKET_EXPIRY_INTERVAL must be positive");
+        }
+        Ok(Self {
+            db_pool: config.db_pool.clone(),
+            ttl: config.bucket_ttl,
+            interval: Duration::from_secs(config.bucket_expiry_interval),
+            dry_run: config.bucket_expiry_dry_run,
+        })
+    }
+
+    pub async fn run_untill_stopped(self) -> Result<(), anyhow::Error> {
+        info!(dry_run = self.dry_run, "Running bucket expiry job");
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            // failed run is retried on the next tick
+            if let Err(error) = self.expire_once().await {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Bucket expiry failed"
+                );
+            }
+        }
+    }
+
+    async fn expire_once(&self) -> Result<(), anyhow::Error> {
+        let mut db_conn = self.db_pool.get().await?;
+        let count =
+            orders_service::expire_abandoned_buckets(&mut db_conn, self.ttl, self.dry_run).await?;
+        if self.dry_run {
+            info!(count, "Abandoned bucket items would be removed");
+        } else {
+            info!(count, "Abandoned bucket items removed");
+        }
+        Ok(())
+    }
+}
+
 pub struct GrpcServer {
     server: Router,
 }
 
 impl GrpcServer {
-    pub async fn build() -> Result<Self, anyhow::Error> {
+    pub async fn build(config: &Config) -> Result<Self, anyhow::Error> {
         info!("Building gRPC Server");
-        let order_service = OrdersService {};
+        let order_service = OrdersService {
+            db_pool: config.db_pool.clone(),
+            token_claims_cache: config.token_claims_cache.clone(),
+            users_channel: config.users_channel.clone(),
+        };
         let server =
             tonic::transport::Server::builder().add_service(OrdersServer::new(order_service));
 
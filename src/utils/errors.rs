@@ -1 +1,81 @@
-// todo!("ERRORS");
+use crate::models::orders_model::OrderStatus;
+use async_graphql::{Error, ErrorExtensions};
+use std::fmt;
+use tonic::{Code, Status};
+use tracing::error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatusError {
+    // Transition is not allowed by order state machine
+    IllegalTransition { from: OrderStatus, to: OrderStatus },
+    // Order status was changed by another request in the meantime
+    Conflict { expected: OrderStatus },
+}
+
+impl fmt::Display for OrderStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderStatusError::IllegalTransition { from, to } => {
+                write!(f, "Order status cannot be changed from {} to {}", from, to)
+            }
+            OrderStatusError::Conflict { expected } => {
+                write!(f, "Order is not in {} status anymore", expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderStatusError {}
+
+impl ErrorExtensions for OrderStatusError {
+    fn extend(&self) -> Error {
+        Error::new(self.to_string()).extend_with(|_, extensions| match self {
+            OrderStatusError::IllegalTransition { from, to } => {
+                extensions.set("code", "ILLEGAL_STATUS_TRANSITION");
+                extensions.set("from", from.as_str());
+                extensions.set("to", to.as_str());
+            }
+            OrderStatusError::Conflict { expected } => {
+                extensions.set("code", "STATUS_CONFLICT");
+                extensions.set("expected", expected.as_str());
+            }
+        })
+    }
+}
+
+// Failed request to users service
+#[derive(Debug, Clone)]
+pub struct UsersServiceError(pub Status);
+
+impl fmt::Display for UsersServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.code() {
+            Code::Unavailable | Code::DeadlineExceeded => {
+                f.write_str("Users service is unavailable, try again later")
+            }
+            // upstream message can contain internal details, it is only logged
+            _ => f.write_str("Users service failed to process request"),
+        }
+    }
+}
+
+impl std::error::Error for UsersServiceError {}
+
+impl ErrorExtensions for UsersServiceError {
+    fn extend(&self) -> Error {
+        error!(
+            code = ?self.0.code(),
+            message = self.0.message(),
+            "Users service request failed"
+        );
+        Error::new(self.to_string()).extend_with(|_, extensions| {
+            match self.0.code() {
+                Code::Unavailable | Code::DeadlineExceeded => {
+                    extensions.set("code", "USERS_SERVICE_UNAVAILABLE")
+                }
+                _ => extensions.set("code", "USERS_SERVICE_ERROR"),
+            }
+            extensions.set("grpcCode", format!("{:?}", self.0.code()));
+        })
+    }
+}
@@ -1,10 +1,14 @@
-use crate::models::orders_model::OrderInfo;
+use crate::models::orders_model::{OrderInfo, Page, TotalCount};
+use crate::services::orders_service::RestaurantScheduleLoader;
 use crate::services::users_service::TokenClaims;
 use crate::{
-    handlers::orders_handler::{Buckets, Orders, Products},
+    handlers::orders_handler::{Buckets, Categories, Orders, Products, Restaurants},
     schema::graphql_schema::{MutationRoot, QueryRoot, SubscriptionRoot},
 };
-use async_graphql::{Context, Error, Schema};
+use async_graphql::connection::{Connection, Edge, OpaqueCursor};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, Error, MaybeUndefined, OutputType, Schema};
+use serde::{de::DeserializeOwned, Serialize};
 use tracing::info;
 use uuid::Uuid;
 
@@ -16,16 +20,117 @@ pub fn build_schema(config: Config) -> Schema<QueryRoot, MutationRoot, Subscript
     Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
-        SubscriptionRoot::default(),
+        SubscriptionRoot,
     )
     .data(Products)
+    .data(Categories)
+    .data(Restaurants)
     .data(Buckets)
     .data(Orders)
+    .data(DataLoader::new(
+        RestaurantScheduleLoader {
+            db_pool: config.db_pool.clone(),
+        },
+        tokio::spawn,
+    ))
     .data(config)
-    .limit_depth(5)
+    // Relay connections add "edges { node }" levels on top of
+    // the depth of 5 which was allowed before pagination
+    .limit_depth(7)
     .finish()
 }
 
+// Converts optional nullable argument into changeset value:
+// None leaves column as is, Some(None) sets it to null
+pub fn maybe_undefined_to_option<T>(value: MaybeUndefined<T>) -> Option<Option<T>> {
+    match value {
+        MaybeUndefined::Undefined => None,
+        MaybeUndefined::Null => Some(None),
+        MaybeUndefined::Value(value) => Some(Some(value)),
+    }
+}
+
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+// Converts Relay connection arguments into keyset page
+pub fn page_from_args<C>(
+    after: Option<OpaqueCursor<C>>,
+    before: Option<OpaqueCursor<C>>,
+    first: Option<usize>,
+    last: Option<usize>,
+) -> Page<C> {
+    let size = first
+        .or(last)
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .min(MAX_PAGE_SIZE);
+    Page {
+        after: after.map(|cursor| cursor.0),
+        before: before.map(|cursor| cursor.0),
+        size,
+        backward: last.is_some(),
+    }
+}
+
+// Builds Relay connection from rows fetched for the page
+pub fn build_connection<C, N>(
+    mut nodes: Vec<N>,
+    page: &Page<C>,
+    total_count: i64,
+    cursor: impl Fn(&N) -> C,
+) -> Connection<OpaqueCursor<C>, N, TotalCount>
+where
+    C: Serialize + DeserializeOwned + Send + Sync,
+    N: OutputType,
+{
+    let has_more = nodes.len() > page.size;
+    nodes.truncate(page.size);
+    if page.backward {
+        nodes.reverse();
+    }
+    let (has_previous_page, has_next_page) = if page.backward {
+        (has_more, page.before.is_some())
+    } else {
+        (page.after.is_some(), has_more)
+    };
+
+    let mut connection = Connection::with_additional_fields(
+        has_previous_page,
+        has_next_page,
+        TotalCount { total_count },
+    );
+    connection.edges.extend(
+        nodes
+            .into_iter()
+            .map(|node| Edge::new(OpaqueCursor(cursor(&node)), node)),
+    );
+    connection
+}
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+// Idempotency key passed through "Idempotency-Key" header
+pub struct IdempotencyKey(pub String);
+
+// Idempotency key from mutation argument has priority over header
+pub fn idempotency_key_from_context(
+    context: &Context<'_>,
+    argument: Option<String>,
+) -> Result<Option<String>, Error> {
+    let key = argument.or_else(|| {
+        context
+            .data_opt::<IdempotencyKey>()
+            .map(|key| key.0.clone())
+    });
+    match key {
+        Some(key) if key.is_empty() || key.len() > IDEMPOTENCY_KEY_MAX_LENGTH => {
+            Err("Invalid idempotency key".into())
+        }
+        key => Ok(key),
+    }
+}
+
 // Get claims from GraphQL context
 // Should not panic because of adding claims to context in GraphQL endpoint
 pub fn token_claims_from_context<'a>(context: &'a Context<'a>) -> &'a TokenClaims {
@@ -35,9 +140,7 @@ pub fn token_claims_from_context<'a>(context: &'a Context<'a>) -> &'a TokenClaim
 }
 
 pub fn policy_from_context<'a>(context: &'a Context<'a>) -> Result<&'a Policy, Error> {
-    Ok(&context
-        .data::<Config>()?
-        .permission_policy)
+    Ok(&context.data::<Config>()?.permission_policy)
 }
 
 pub fn has_access(permissions: &[String], context: &Context<'_>) -> bool {
@@ -55,7 +158,10 @@ pub async fn has_access_to_order(
     let order_user_uuid = order.user_uuid;
     let order_courier_uuid = order.courier_uuid;
 
-    if has_access(permissions, context) || uuid == order_user_uuid || uuid == order_courier_uuid {
+    if has_access(permissions, context)
+        || uuid == order_user_uuid
+        || order_courier_uuid == Some(uuid)
+    {
         Ok(())
     } else {
         Err("Forbidden".into())
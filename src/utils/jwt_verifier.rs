@@ -0,0 +1,316 @@
+use crate::services::users_service::TokenClaims;
+use anyhow::{bail, Context};
+use jsonwebtoken::errors::{Error, ErrorKind};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+// Where bearer tokens are verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    // Users service returns claims of token
+    Grpc,
+    // Signature and claims of token are checked by this service,
+    // so it keeps working while users service is down
+    Local,
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "grpc" => Ok(AuthMode::Grpc),
+            "local" => Ok(AuthMode::Local),
+            _ => Err(format!(
+                "Unknown auth mode \"{}\", expected \"grpc\" or \"local\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AuthMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMode::Grpc => f.write_str("grpc"),
+            AuthMode::Local => f.write_str("local"),
+        }
+    }
+}
+
+// Claims issued by users service,
+// "sub" is taken as user uuid when token has no "uuid" claim
+#[derive(Deserialize)]
+struct JwtClaims {
+    uuid: Option<Uuid>,
+    sub: Option<String>,
+    role: String,
+}
+
+struct VerificationKey {
+    key_id: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    audience: Option<String>,
+}
+
+impl JwtVerifier {
+    // HS256 key is built from shared secret,
+    // RS256 and ES256 public keys are loaded from JWKS file
+    pub fn new(
+        secret: Option<&str>,
+        jwks_path: Option<&str>,
+        audience: Option<String>,
+    ) -> Result<Self, anyhow::Error> {
+        let jwks = match jwks_path {
+            Some(path) => {
+                let jwks = std::fs::read_to_string(path)
+                    .with_context(|| format!("Cannot read JWKS file {}", path))?;
+                let jwks: JwkSet = serde_json::from_str(&jwks)
+                    .with_context(|| format!("Cannot parse JWKS file {}", path))?;
+                Some(jwks)
+            }
+            None => None,
+        };
+        Self::from_jwks(secret, jwks.as_ref(), audience)
+    }
+
+    fn from_jwks(
+        secret: Option<&str>,
+        jwks: Option<&JwkSet>,
+        audience: Option<String>,
+    ) -> Result<Self, anyhow::Error> {
+        let mut keys = Vec::new();
+        if let Some(secret) = secret {
+            keys.push(VerificationKey {
+                key_id: None,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            });
+        }
+        if let Some(jwks) = jwks {
+            for jwk in &jwks.keys {
+                let algorithm = match &jwk.algorithm {
+                    AlgorithmParameters::RSA(_) => Algorithm::RS256,
+                    AlgorithmParameters::EllipticCurve(params)
+                        if params.curve == EllipticCurve::P256 =>
+                    {
+                        Algorithm::ES256
+                    }
+                    _ => {
+                        tracing::warn!(key_id = ?jwk.common.key_id, "Unsupported JWKS key skipped");
+                        continue;
+                    }
+                };
+                if jwk.common.algorithm.is_some_and(|alg| alg != algorithm) {
+                    tracing::warn!(key_id = ?jwk.common.key_id, "Unsupported JWKS key skipped");
+                    continue;
+                }
+                keys.push(VerificationKey {
+                    key_id: jwk.common.key_id.clone(),
+                    algorithm,
+                    key: DecodingKey::from_jwk(jwk)?,
+                });
+            }
+        }
+        if keys.is_empty() {
+            bail!("Local auth mode requires JWT_SECRET or JWKS_PATH with supported keys");
+        }
+        Ok(Self { keys, audience })
+    }
+
+    // Checks signature, "exp", "nbf" and "aud" if audience is configured
+    pub fn verify(&self, token: &str) -> Result<TokenClaims, Error> {
+        let header = decode_header(token)?;
+        let mut validation = Validation::new(header.alg);
+        validation.validate_nbf = true;
+        if let Some(audience) = &self.audience {
+            validation.set_audience(&[audience]);
+        }
+
+        let mut result = Err(ErrorKind::InvalidAlgorithm.into());
+        // token without "kid" is checked against every key of its algorithm
+        let keys = self.keys.iter().filter(|key| {
+            key.algorithm == header.alg
+                && (header.kid.is_none() || key.key_id.is_none() || key.key_id == header.kid)
+        });
+        for key in keys {
+            result = decode::<JwtClaims>(token, &key.key, &validation);
+            match &result {
+                Err(error) if *error.kind() == ErrorKind::InvalidSignature => continue,
+                _ => break,
+            }
+        }
+        let claims = result?.claims;
+        let uuid = match (claims.uuid, claims.sub) {
+            (Some(uuid), _) => uuid,
+            (None, Some(sub)) => Uuid::parse_str(&sub).map_err(|_| ErrorKind::InvalidSubject)?,
+            (None, None) => return Err(ErrorKind::MissingRequiredClaim("uuid".into()).into()),
+        };
+        Ok(TokenClaims {
+            uuid,
+            role: claims.role,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    const SECRET: &str = "test-secret";
+    const AUDIENCE: &str = "delivery";
+    const RSA_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/jwt_rsa_private.pem");
+    const EC_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/jwt_ec_private.pem");
+    const JWKS: &str = include_str!("../../tests/fixtures/jwt_jwks.json");
+
+    fn verifier() -> JwtVerifier {
+        let jwks: JwkSet = serde_json::from_str(JWKS).unwrap();
+        JwtVerifier::from_jwks(Some(SECRET), Some(&jwks), Some(AUDIENCE.to_string())).unwrap()
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn claims(uuid: Uuid) -> Value {
+        json!({
+            "uuid": uuid,
+            "role": "USER",
+            "aud": AUDIENCE,
+            "exp": now() + 600,
+        })
+    }
+
+    fn sign(algorithm: Algorithm, key_id: Option<&str>, claims: &Value) -> String {
+        let key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(RSA_PRIVATE_KEY.as_bytes()).unwrap(),
+            Algorithm::ES256 => EncodingKey::from_ec_pem(EC_PRIVATE_KEY.as_bytes()).unwrap(),
+            _ => EncodingKey::from_secret(SECRET.as_bytes()),
+        };
+        let mut header = Header::new(algorithm);
+        header.kid = key_id.map(str::to_string);
+        encode(&header, claims, &key).unwrap()
+    }
+
+    #[test]
+    fn accepts_hs256_token() {
+        let uuid = Uuid::new_v4();
+        let token = sign(Algorithm::HS256, None, &claims(uuid));
+
+        let claims = verifier().verify(&token).unwrap();
+        assert_eq!(claims.uuid, uuid);
+        assert_eq!(claims.role, "USER");
+    }
+
+    #[test]
+    fn accepts_rs256_token() {
+        let uuid = Uuid::new_v4();
+        let token = sign(Algorithm::RS256, Some("rsa-test"), &claims(uuid));
+
+        assert_eq!(verifier().verify(&token).unwrap().uuid, uuid);
+    }
+
+    #[test]
+    fn accepts_es256_token() {
+        let uuid = Uuid::new_v4();
+        let token = sign(Algorithm::ES256, Some("ec-test"), &claims(uuid));
+
+        assert_eq!(verifier().verify(&token).unwrap().uuid, uuid);
+    }
+
+    #[test]
+    fn accepts_token_without_key_id() {
+        let uuid = Uuid::new_v4();
+        let token = sign(Algorithm::ES256, None, &claims(uuid));
+
+        assert_eq!(verifier().verify(&token).unwrap().uuid, uuid);
+    }
+
+    #[test]
+    fn prefers_uuid_claim_over_sub() {
+        let uuid = Uuid::new_v4();
+        let mut claims = claims(uuid);
+        claims["sub"] = json!("user@example.com");
+        let token = sign(Algorithm::HS256, None, &claims);
+
+        assert_eq!(verifier().verify(&token).unwrap().uuid, uuid);
+    }
+
+    #[test]
+    fn takes_uuid_from_sub() {
+        let uuid = Uuid::new_v4();
+        let mut claims = claims(uuid);
+        claims["sub"] = claims["uuid"].take();
+        let token = sign(Algorithm::HS256, None, &claims);
+
+        assert_eq!(verifier().verify(&token).unwrap().uuid, uuid);
+    }
+
+    #[test]
+    fn rejects_sub_which_is_not_uuid() {
+        let mut claims = claims(Uuid::new_v4());
+        claims["uuid"] = Value::Null;
+        claims["sub"] = json!("user@example.com");
+        let token = sign(Algorithm::HS256, None, &claims);
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidSubject);
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let mut claims = claims(Uuid::new_v4());
+        claims["exp"] = json!(now() - 600);
+        let token = sign(Algorithm::RS256, Some("rsa-test"), &claims);
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::ExpiredSignature);
+    }
+
+    #[test]
+    fn rejects_token_before_nbf() {
+        let mut claims = claims(Uuid::new_v4());
+        claims["nbf"] = json!(now() + 300);
+        let token = sign(Algorithm::ES256, Some("ec-test"), &claims);
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::ImmatureSignature);
+    }
+
+    #[test]
+    fn rejects_wrong_audience() {
+        let mut claims = claims(Uuid::new_v4());
+        claims["aud"] = json!("other-service");
+        let token = sign(Algorithm::HS256, None, &claims);
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidAudience);
+    }
+
+    #[test]
+    fn rejects_algorithm_without_key() {
+        let token = sign(Algorithm::HS384, None, &claims(Uuid::new_v4()));
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidAlgorithm);
+    }
+
+    #[test]
+    fn rejects_unknown_key_id() {
+        let token = sign(Algorithm::RS256, Some("unknown"), &claims(Uuid::new_v4()));
+
+        let error = verifier().verify(&token).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidAlgorithm);
+    }
+}
@@ -2,5 +2,7 @@ pub mod configs;
 pub mod errors;
 pub mod graphql_utils;
 pub mod grpc;
+pub mod jwt_verifier;
 pub mod permission_policy;
 pub mod simple_broker;
+pub mod token_claims_cache;
@@ -7,6 +7,7 @@ pub struct Policy {
     pub courier_policy: Vec<String>,
     pub admin_policy: Vec<String>,
     pub analyst_policy: Vec<String>,
+    pub restaurant_policy: Vec<String>,
 }
 
 impl Policy {
@@ -22,6 +23,7 @@ impl Policy {
             courier_policy: vec!["COURIER".to_owned(), "ADMIN".to_owned()],
             admin_policy: vec!["ADMIN".to_owned()],
             analyst_policy: vec!["ANALYST".to_owned(), "ADMIN".to_owned()],
+            restaurant_policy: vec!["RESTAURANT".to_owned(), "ADMIN".to_owned()],
         }
     }
 }
@@ -0,0 +1,402 @@
+use crate::services::users_service::TokenClaims;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type TokenHash = [u8; 32];
+
+struct CachedClaims {
+    claims: TokenClaims,
+    expires_at: Instant,
+}
+
+// Revoked hashes are kept after their claims left the cache,
+// so claims received for revoked token are never cached again.
+// All hashes live for the same time, so the oldest one always expires first.
+// When capacity is reached the oldest revocation is forgotten
+struct RevokedTokens {
+    expires_at: HashMap<TokenHash, Instant>,
+    order: VecDeque<(TokenHash, Instant)>,
+    capacity: usize,
+}
+
+impl RevokedTokens {
+    fn new(capacity: usize) -> Self {
+        Self {
+            expires_at: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    // Token revoked again keeps its first expiration time,
+    // so every hash has a single place in "order"
+    fn insert(&mut self, key: TokenHash, now: Instant, ttl: Duration) {
+        self.remove_expired(now);
+        if self.capacity == 0 || self.expires_at.contains_key(&key) {
+            return;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.expires_at.remove(&oldest);
+                tracing::warn!("Revoked tokens limit is reached, oldest revocation is forgotten");
+            }
+        }
+        self.expires_at.insert(key, now + ttl);
+        self.order.push_back((key, now + ttl));
+    }
+
+    fn contains(&mut self, key: &TokenHash, now: Instant) -> bool {
+        self.remove_expired(now);
+        self.expires_at.contains_key(key)
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        while let Some((key, expires_at)) = self.order.front().copied() {
+            if expires_at > now {
+                break;
+            }
+            self.order.pop_front();
+            self.expires_at.remove(&key);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.expires_at.len()
+    }
+}
+
+struct Entries {
+    claims: LruCache<TokenHash, CachedClaims>,
+    revoked: RevokedTokens,
+}
+
+// Token claims received from users service.
+// Tokens are stored only as hashes, so memory dump doesn't leak them
+pub struct TokenClaimsCache {
+    entries: Mutex<Entries>,
+    ttl: Duration,
+    revocation_ttl: Duration,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    revocations: AtomicU64,
+}
+
+impl TokenClaimsCache {
+    // Revoked tokens are remembered at least as long as claims are cached
+    pub fn new(
+        ttl: Duration,
+        capacity: usize,
+        revocation_ttl: Duration,
+        revocation_capacity: usize,
+    ) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                claims: LruCache::new(capacity),
+                revoked: RevokedTokens::new(revocation_capacity),
+            }),
+            ttl,
+            revocation_ttl: revocation_ttl.max(ttl),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            revocations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, token: &str) -> Option<TokenClaims> {
+        let claims = self.get_at(token, Instant::now());
+        match &claims {
+            Some(claims) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!(user_uuid = %claims.uuid, "Token claims cache hit");
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Token claims cache miss");
+            }
+        }
+        claims
+    }
+
+    fn get_at(&self, token: &str, now: Instant) -> Option<TokenClaims> {
+        let key = hash_token(token);
+        let mut entries = self.entries.lock().expect("Token claims cache is poisoned");
+        if entries.revoked.contains(&key, now) {
+            entries.claims.pop(&key);
+            return None;
+        }
+        match entries.claims.get(&key) {
+            Some(entry) if entry.expires_at > now => Some(entry.claims.clone()),
+            Some(_) => {
+                entries.claims.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Tokens verified locally are checked against revoked ones as well
+    pub fn is_revoked(&self, token: &str) -> bool {
+        let key = hash_token(token);
+        self.is_revoked_at(&key, Instant::now())
+    }
+
+    fn is_revoked_at(&self, key: &TokenHash, now: Instant) -> bool {
+        self.entries
+            .lock()
+            .expect("Token claims cache is poisoned")
+            .revoked
+            .contains(key, now)
+    }
+
+    // Claims of revoked token are not cached.
+    // When cache is full the least recently used entry is dropped
+    pub fn insert(&self, token: &str, claims: TokenClaims) {
+        self.insert_at(token, claims, Instant::now());
+    }
+
+    fn insert_at(&self, token: &str, claims: TokenClaims, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = hash_token(token);
+        let mut entries = self.entries.lock().expect("Token claims cache is poisoned");
+        if entries.revoked.contains(&key, now) {
+            tracing::debug!("Claims of revoked token are not cached");
+            return;
+        }
+        entries.claims.put(
+            key,
+            CachedClaims {
+                claims,
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+
+    // Returns true if claims of token were cached
+    pub fn revoke(&self, token: &str) -> bool {
+        let removed = self.revoke_at(token, Instant::now());
+        self.revocations.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(was_cached = removed, "Token revoked");
+        removed
+    }
+
+    fn revoke_at(&self, token: &str, now: Instant) -> bool {
+        let key = hash_token(token);
+        let mut entries = self.entries.lock().expect("Token claims cache is poisoned");
+        entries.revoked.insert(key, now, self.revocation_ttl);
+        entries.claims.pop(&key).is_some()
+    }
+
+    // Counters in Prometheus text format
+    pub fn metrics(&self) -> String {
+        let (cached, revoked) = {
+            let entries = self.entries.lock().expect("Token claims cache is poisoned");
+            (entries.claims.len(), entries.revoked.len())
+        };
+        let counters = [
+            (
+                "token_claims_cache_hits_total",
+                "counter",
+                self.hits.load(Ordering::Relaxed),
+            ),
+            (
+                "token_claims_cache_misses_total",
+                "counter",
+                self.misses.load(Ordering::Relaxed),
+            ),
+            (
+                "token_claims_cache_revocations_total",
+                "counter",
+                self.revocations.load(Ordering::Relaxed),
+            ),
+            ("token_claims_cache_entries", "gauge", cached as u64),
+            ("token_claims_cache_revoked_tokens", "gauge", revoked as u64),
+        ];
+        let mut metrics = String::new();
+        for (name, kind, value) in counters {
+            // writing into String cannot fail
+            let _ = writeln!(metrics, "# TYPE {} {}\n{} {}", name, kind, name, value);
+        }
+        metrics
+    }
+}
+
+fn hash_token(token: &str) -> TokenHash {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn claims() -> TokenClaims {
+        TokenClaims {
+            uuid: Uuid::new_v4(),
+            role: "USER".to_string(),
+        }
+    }
+
+    #[test]
+    fn returns_inserted_claims() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+        let claims = claims();
+
+        cache.insert_at("token", claims.clone(), now);
+
+        let cached = cache.get_at("token", now).unwrap();
+        assert_eq!(cached.uuid, claims.uuid);
+        assert!(cache.get_at("other", now).is_none());
+    }
+
+    #[test]
+    fn expired_claims_are_not_returned() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+
+        cache.insert_at("token", claims(), now);
+
+        assert!(cache
+            .get_at("token", now + TTL - Duration::from_secs(1))
+            .is_some());
+        assert!(cache.get_at("token", now + TTL).is_none());
+    }
+
+    #[test]
+    fn least_recently_used_claims_are_evicted() {
+        let cache = TokenClaimsCache::new(TTL, 2, TTL, 10);
+        let now = Instant::now();
+
+        cache.insert_at("first", claims(), now);
+        cache.insert_at("second", claims(), now);
+        // "second" becomes least recently used
+        assert!(cache.get_at("first", now).is_some());
+        cache.insert_at("third", claims(), now);
+
+        assert!(cache.get_at("first", now).is_some());
+        assert!(cache.get_at("second", now).is_none());
+        assert!(cache.get_at("third", now).is_some());
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let cache = TokenClaimsCache::new(TTL, 0, TTL, 10);
+        let now = Instant::now();
+
+        cache.insert_at("token", claims(), now);
+
+        assert!(cache.get_at("token", now).is_none());
+    }
+
+    #[test]
+    fn revoke_removes_cached_claims() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+
+        cache.insert_at("token", claims(), now);
+
+        assert!(cache.revoke_at("token", now));
+        assert!(cache.get_at("token", now).is_none());
+        assert!(!cache.revoke_at("token", now));
+    }
+
+    #[test]
+    fn revoked_token_is_reported_without_cached_claims() {
+        let cache = TokenClaimsCache::new(TTL, 0, TTL, 10);
+
+        cache.revoke("token");
+
+        assert!(cache.is_revoked("token"));
+        assert!(!cache.is_revoked("other"));
+    }
+
+    #[test]
+    fn claims_of_token_revoked_before_insert_are_not_cached() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+
+        // claims request was in flight while token was revoked
+        assert!(!cache.revoke_at("token", now));
+        cache.insert_at("token", claims(), now + Duration::from_secs(1));
+
+        assert!(cache
+            .get_at("token", now + Duration::from_secs(1))
+            .is_none());
+    }
+
+    #[test]
+    fn revocation_lives_at_least_as_long_as_claims() {
+        let cache = TokenClaimsCache::new(TTL, 10, Duration::from_secs(1), 10);
+        let now = Instant::now();
+
+        cache.revoke_at("token", now);
+        cache.insert_at("token", claims(), now + TTL - Duration::from_secs(1));
+        assert!(cache
+            .get_at("token", now + TTL - Duration::from_secs(1))
+            .is_none());
+
+        // token can be cached again after revocation expired
+        cache.insert_at("token", claims(), now + TTL);
+        assert!(cache.get_at("token", now + TTL).is_some());
+    }
+
+    #[test]
+    fn expired_revocations_are_removed() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+
+        cache.revoke_at("first", now);
+        cache.revoke_at("second", now + Duration::from_secs(1));
+        cache.revoke_at("second", now + TTL);
+
+        let mut entries = cache.entries.lock().unwrap();
+        assert!(entries.revoked.contains(&hash_token("second"), now + TTL));
+        assert_eq!(entries.revoked.len(), 1);
+    }
+
+    #[test]
+    fn expired_revocations_are_removed_on_lookup() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 10);
+        let now = Instant::now();
+
+        cache.revoke_at("first", now);
+        cache.revoke_at("second", now);
+
+        assert!(!cache.is_revoked_at(&hash_token("other"), now + TTL));
+        assert_eq!(cache.entries.lock().unwrap().revoked.len(), 0);
+
+        cache.revoke_at("third", now + TTL);
+        assert!(cache.get_at("token", now + TTL * 2).is_none());
+        assert_eq!(cache.entries.lock().unwrap().revoked.len(), 0);
+    }
+
+    #[test]
+    fn oldest_revocation_is_forgotten_when_full() {
+        let cache = TokenClaimsCache::new(TTL, 10, TTL, 2);
+        let now = Instant::now();
+
+        cache.revoke_at("first", now);
+        cache.revoke_at("second", now);
+        // revoking the same token again doesn't take another place
+        cache.revoke_at("second", now);
+        cache.revoke_at("third", now);
+
+        assert!(!cache.is_revoked_at(&hash_token("first"), now));
+        assert!(cache.is_revoked_at(&hash_token("second"), now));
+        assert!(cache.is_revoked_at(&hash_token("third"), now));
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.revoked.len(), 2);
+        assert_eq!(entries.revoked.order.len(), 2);
+    }
+}